@@ -1,7 +1,12 @@
-use std::{collections::HashMap, vec};
-use axum::http::response;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot};
-use crate::domain::{Trade, User};
+use uuid::Uuid;
+use crate::domain::ledger::{credit, debit};
+use crate::domain::{
+    Account, AssetBalance, ConservationReport, Ledger, PayoutProvider, SelfTradePrevention, Side, Trade, Transaction, TransactionKind, User, Withdrawal,
+    WithdrawalPolicy, WithdrawalStatus,
+};
 
 pub type DbSender = mpsc::Sender<DbCommand>;
 
@@ -16,10 +21,11 @@ pub enum DbCommand {
         password: String,
         response_status: oneshot::Sender<SigninResponseType>
     },
+    /// Credits `amount` of `asset` to the user's available balance
     OnRamp {
         user_email: String,
-        delta_balance: u64,
-        delta_holdings: u64,
+        asset: String,
+        amount: u64,
         response_status: oneshot::Sender<OnRampDbResponseType>
     },
     CheckUser {
@@ -30,9 +36,75 @@ pub enum DbCommand {
         user_email: String,
         response_status: oneshot::Sender<GetUserDbResponseType>
     },
+    SetSelfTradePrevention {
+        user_email: String,
+        mode: Option<SelfTradePrevention>,
+        response_status: oneshot::Sender<SetSelfTradePreventionDbResponseType>
+    },
+    /// Settles trades of one market, the buyer pays `quote_asset` for `base_asset`.
+    /// Either every leg of every trade is applied or, when one cannot be paid for, none is.
     Reconciliation{
         trades: Vec<Trade>,
-        response_status: oneshot::Sender<Vec<ReconciliationDbResponseType>>
+        base_asset: String,
+        quote_asset: String,
+        response_status: oneshot::Sender<ReconciliationDbResponseType>
+    },
+    /// Locks `amount` of `asset` for an order, the quote asset for a bid and the base asset
+    /// for an ask. Whatever the order held before is swapped for the new amount.
+    Reserve {
+        order_id: Uuid,
+        user_email: String,
+        asset: String,
+        amount: u64,
+        response_status: oneshot::Sender<ReserveDbResponseType>
+    },
+    /// Hands back whatever the orders still hold
+    Release {
+        order_ids: Vec<Uuid>,
+        response_status: oneshot::Sender<ReleaseDbResponseType>
+    },
+    /// Ledger transactions that touched the user, with only the user's own entries
+    GetLedger {
+        user_email: String,
+        response_status: oneshot::Sender<GetLedgerDbResponseType>
+    },
+    /// Checks user balances against the ledger and that every asset is conserved
+    CheckLedger {
+        response_status: oneshot::Sender<ConservationReport>
+    },
+    /// Holds `amount` of `asset` for a payout to `destination`. Amounts over the policy's
+    /// threshold wait for an admin, the rest are paid out straight away.
+    Withdraw {
+        user_email: String,
+        asset: String,
+        amount: u64,
+        destination: String,
+        response_status: oneshot::Sender<WithdrawalDbResponseType>
+    },
+    /// Approves and pays out, or rejects and releases, a withdrawal. Approving one that is
+    /// already approved retries its payout. Neither is allowed while a payout is in flight.
+    ReviewWithdrawal {
+        admin_email: String,
+        withdrawal_id: Uuid,
+        approve: bool,
+        reason: Option<String>,
+        response_status: oneshot::Sender<WithdrawalDbResponseType>
+    },
+    /// Every withdrawal of the user, oldest first
+    ListWithdrawals {
+        user_email: String,
+        response_status: oneshot::Sender<ListWithdrawalsDbResponseType>
+    },
+    /// Withdrawals waiting for an admin, only answered for admins
+    PendingWithdrawals {
+        admin_email: String,
+        response_status: oneshot::Sender<ListWithdrawalsDbResponseType>
+    },
+    /// What the payout provider answered for a payout the actor started. Sent by the actor's
+    /// own payout task, nothing waits for a response.
+    PayoutResult {
+        withdrawal_id: Uuid,
+        result: Result<String, String>,
     }
 }
 
@@ -46,8 +118,7 @@ pub struct SigninResponseType {
 
 pub struct OnRampDbResponseType {
     pub status: String,
-    pub balance: u64,
-    pub holdings: u64
+    pub balances: BTreeMap<String, AssetBalance>,
 }
 
 pub struct CheckUserDbResponseType {
@@ -58,8 +129,61 @@ pub struct GetUserDbResponseType{
     pub user: Option<User>
 }
 
+pub struct SetSelfTradePreventionDbResponseType {
+    pub status: String,
+}
+
+pub struct ReserveDbResponseType {
+    pub status: String,
+    /// Whether the order now holds the amount asked for
+    pub reserved: bool,
+    /// What the order held before, still held when the reservation was refused
+    pub held: u64,
+}
+
+pub struct ReleaseDbResponseType {
+    /// Number of reservations handed back
+    pub released: usize,
+}
+
+pub struct GetLedgerDbResponseType {
+    pub user_exists: bool,
+    pub transactions: Vec<Transaction>,
+}
+
+pub struct WithdrawalDbResponseType {
+    pub status: String,
+    pub withdrawal: Option<Withdrawal>,
+}
+
+pub struct ListWithdrawalsDbResponseType {
+    pub status: String,
+    pub withdrawals: Vec<Withdrawal>,
+}
+
+/// Funds an open order has locked, drawn down as it fills
+struct Reservation {
+    user_email: String,
+    asset: String,
+    amount: u64,
+}
+
+pub struct ReconciliationDbResponseType {
+    pub status: String,
+    /// Balances before and after each trade, or the leg that stopped anything from settling
+    pub outcome: Result<Vec<Settlement>, SettlementFailure>,
+}
+
+/// Leg of a trade that could not be paid for
 #[derive(Debug)]
-pub struct ReconciliationDbResponseType{
+pub struct SettlementFailure {
+    /// Order on the paying side of the leg
+    pub order_id: Uuid,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct Settlement{
     pub trade: Trade,
     pub buyer: String,
     pub seller: String,
@@ -67,8 +191,14 @@ pub struct ReconciliationDbResponseType{
     pub curr_balances : Vec<User>
 }
 
-pub async fn start_db_actor(mut rx: mpsc::Receiver<DbCommand>) {
+/// Runs the DB actor. `db_tx` is the sender of `rx`, payouts post their result back through it.
+/// Only a weak handle is kept, so the actor still stops once every other sender is gone.
+pub async fn start_db_actor(mut rx: mpsc::Receiver<DbCommand>, db_tx: DbSender, withdrawal_policy: WithdrawalPolicy, payouts: Arc<dyn PayoutProvider>) {
+    let db_tx = db_tx.downgrade();
     let mut users: HashMap<String, User> = HashMap::new();
+    let mut reservations: HashMap<Uuid, Reservation> = HashMap::new();
+    let mut ledger = Ledger::new();
+    let mut withdrawals: Vec<Withdrawal> = Vec::new();
 
     println!("UserDBActor started");
 
@@ -112,20 +242,18 @@ pub async fn start_db_actor(mut rx: mpsc::Receiver<DbCommand>) {
 
                 let _ = response_status.send(response);
             },
-            DbCommand::OnRamp { user_email, delta_balance, delta_holdings, response_status } => {
+            DbCommand::OnRamp { user_email, asset, amount, response_status } => {
                 let status: OnRampDbResponseType = if let Some(user) = users.get_mut(&user_email) {
-                    user.balance += delta_balance;
-                    user.holdings += delta_holdings;
+                    user.credit(&asset, amount);
+                    ledger.transfer(TransactionKind::OnRamp, None, &asset, Account::External, Account::Available(user_email.clone()), amount);
                     OnRampDbResponseType { 
-                        status: format!("Successfull! User {} now has balance : {} , holding: {} ", user.email, user.balance, user.holdings), 
-                        balance: user.balance, 
-                        holdings: user.holdings 
+                        status: format!("Successfull! User {} now has {} {} available", user.email, user.balance(&asset).available, asset), 
+                        balances: user.balances.clone(),
                     }
                 } else {
                     OnRampDbResponseType { 
                         status: format!("User not found! User: {} found", user_email).to_string(), 
-                        balance: 0, 
-                        holdings: 0
+                        balances: BTreeMap::new(),
                     }
                 };
                 let _ = response_status.send(status);
@@ -143,46 +271,331 @@ pub async fn start_db_actor(mut rx: mpsc::Receiver<DbCommand>) {
                 };
                 let _ = response_status.send(response);
             }
-            DbCommand::Reconciliation {trades, response_status} => {
-                
-                let mut responses = Vec::new();
-                for trade in trades {
+            DbCommand::SetSelfTradePrevention { user_email, mode, response_status } => {
+                let status = if let Some(user) = users.get_mut(&user_email) {
+                    user.self_trade_prevention = mode;
+                    format!("Successfull! User {} self-trade prevention set to {:?}", user.email, mode)
+                } else {
+                    format!("User not found! User: {}", user_email)
+                };
+                let _ = response_status.send(SetSelfTradePreventionDbResponseType { status });
+            }
+            DbCommand::Reconciliation {trades, base_asset, quote_asset, response_status} => {
+                // every leg is settled on copies first, nothing is applied unless all of them go through
+                let mut staged_users: HashMap<String, User> = HashMap::new();
+                let mut staged_reservations: HashMap<Uuid, u64> = HashMap::new();
+                let mut journal = Vec::new();
+                let mut settlements = Vec::new();
+                let mut failure = None;
+
+                'trades: for trade in trades {
+                    // the buy order is the taker unless a sell order took liquidity
+                    let (buy_order, sell_order) = match trade.aggressor_side {
+                        Some(Side::Ask) => (trade.maker_order_id, trade.taker_order_id),
+                        _ => (trade.taker_order_id, trade.maker_order_id),
+                    };
+                    let Some(cost) = trade.price.checked_mul(trade.qty) else {
+                        failure = Some(SettlementFailure { order_id: buy_order, reason: format!("Trade {} notional overflows", trade.id) });
+                        break;
+                    };
+
+                    let legs = [
+                        (&trade.buyer, buy_order, &quote_asset, cost, &base_asset, trade.qty),
+                        (&trade.seller, sell_order, &base_asset, trade.qty, &quote_asset, cost),
+                    ];
                     let mut prev_balances: Vec<User> = Vec::new();
                     let mut curr_balances: Vec<User> = Vec::new();
-                    
-                    if let Some(buyer) = users.get_mut(&trade.buyer){
-                        if buyer.balance > trade.price{
-                            prev_balances.push(buyer.clone());
-                            buyer.holdings += trade.qty;
-                            buyer.balance -= trade.price*trade.qty;
-                        }
-                        curr_balances.push(buyer.clone());
-                    }
-                    if let Some(seller) = users.get_mut(&trade.seller){
-                        if seller.holdings > trade.qty{
-                            prev_balances.push(seller.clone());
-                            seller.holdings -= trade.qty;
-                            seller.balance += trade.price*trade.qty;
+                    let mut entries = Vec::new();
+
+                    for (email, order_id, pays, amount, receives, proceeds) in legs {
+                        if !staged_users.contains_key(email) && let Some(user) = users.get(email) {
+                            staged_users.insert(email.clone(), user.clone());
                         }
-                        curr_balances.push(seller.clone());
+                        let Some(user) = staged_users.get_mut(email) else {
+                            failure = Some(SettlementFailure { order_id, reason: format!("User not found! User: {}", email) });
+                            break 'trades;
+                        };
+                        let reservation = staged_reservations
+                            .entry(order_id)
+                            .or_insert_with(|| reservations.get(&order_id).map(|r| r.amount).unwrap_or(0));
+
+                        prev_balances.push(user.clone());
+                        let from_reserved = match settle_leg(user, reservation, pays, amount, receives, proceeds) {
+                            Ok(from_reserved) => from_reserved,
+                            Err(reason) => {
+                                failure = Some(SettlementFailure { order_id, reason: format!("{} to settle trade {}", reason, trade.id) });
+                                break 'trades;
+                            }
+                        };
+                        curr_balances.push(user.clone());
+
+                        entries.extend([
+                            debit(Account::Reserved(email.clone()), pays, from_reserved),
+                            debit(Account::Available(email.clone()), pays, amount - from_reserved),
+                            credit(Account::Available(email.clone()), receives, proceeds),
+                        ]);
                     }
 
-                    let response = ReconciliationDbResponseType {
+                    journal.push((trade.id, entries));
+                    settlements.push(Settlement {
                         buyer: trade.buyer.clone(),
                         seller: trade.seller.clone(),
-                        trade: trade,
-                        prev_balances: prev_balances,
+                        trade,
+                        prev_balances,
                         curr_balances
-                    };
-
-                    responses.push(response);
+                    });
                 }
-                
 
-                let _ = response_status.send(responses);
+                let response = match failure {
+                    Some(failure) => {
+                        println!("Reconciliation refused, nothing applied: {}", failure.reason);
+                        ReconciliationDbResponseType { status: failure.reason.clone(), outcome: Err(failure) }
+                    }
+                    None => {
+                        users.extend(staged_users);
+                        for (order_id, amount) in staged_reservations {
+                            if let Some(reservation) = reservations.get_mut(&order_id) {
+                                reservation.amount = amount;
+                            }
+                        }
+                        for (trade_id, entries) in journal {
+                            if let Err(reason) = ledger.post(TransactionKind::Trade, Some(trade_id), entries) {
+                                println!("Trade {} not journaled: {}", trade_id, reason);
+                            }
+                        }
+                        ReconciliationDbResponseType {
+                            status: format!("Success, {} trades settled", settlements.len()),
+                            outcome: Ok(settlements),
+                        }
+                    }
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::Reserve { order_id, user_email, asset, amount, response_status } => {
+                // an order only ever locks one asset, the one its side pays with
+                let held = reservations.get(&order_id).map(|r| r.amount).unwrap_or(0);
+                let (status, reserved) = if let Some(user) = users.get_mut(&user_email) {
+                    user.release(&asset, held);
+
+                    if user.reserve(&asset, amount) {
+                        let (available, reserved) = (Account::Available(user_email.clone()), Account::Reserved(user_email.clone()));
+                        if amount >= held {
+                            ledger.transfer(TransactionKind::Reserve, Some(order_id), &asset, available, reserved, amount - held);
+                        } else {
+                            ledger.transfer(TransactionKind::Release, Some(order_id), &asset, reserved, available, held - amount);
+                        }
 
+                        let status = format!("Success, {} {} reserved", amount, asset);
+                        reservations.insert(order_id, Reservation { user_email, asset, amount });
+                        (status, true)
+                    } else {
+                        // put back what the order held, it keeps its old reservation
+                        user.reserve(&asset, held);
+                        (format!("Insufficient {} balance", asset), false)
+                    }
+                } else {
+                    ("User does not exist".to_string(), false)
+                };
+                let _ = response_status.send(ReserveDbResponseType { status, reserved, held });
+            }
+            DbCommand::Release { order_ids, response_status } => {
+                let mut released = 0;
+                for order_id in order_ids {
+                    if let Some(reservation) = reservations.remove(&order_id)
+                        && let Some(user) = users.get_mut(&reservation.user_email)
+                    {
+                        user.release(&reservation.asset, reservation.amount);
+                        ledger.transfer(
+                            TransactionKind::Release,
+                            Some(order_id),
+                            &reservation.asset,
+                            Account::Reserved(reservation.user_email.clone()),
+                            Account::Available(reservation.user_email),
+                            reservation.amount,
+                        );
+                        released += 1;
+                    }
+                }
+                let _ = response_status.send(ReleaseDbResponseType { released });
+            }
+            DbCommand::GetLedger { user_email, response_status } => {
+                let response = GetLedgerDbResponseType {
+                    user_exists: users.contains_key(&user_email),
+                    transactions: ledger.user_transactions(&user_email),
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::CheckLedger { response_status } => {
+                let _ = response_status.send(ledger.check_conservation(users.values()));
+            }
+            DbCommand::Withdraw { user_email, asset, amount, destination, response_status } => {
+                let response = if amount == 0 {
+                    WithdrawalDbResponseType { status: "Withdrawal amount must be greater than zero".to_string(), withdrawal: None }
+                } else if destination.trim().is_empty() {
+                    WithdrawalDbResponseType { status: "Withdrawal destination is required".to_string(), withdrawal: None }
+                } else if let Some(user) = users.get_mut(&user_email) {
+                    if user.reserve(&asset, amount) {
+                        let requires_approval = withdrawal_policy.needs_approval(&asset, amount);
+                        let mut withdrawal = Withdrawal::new(user_email.clone(), asset, amount, destination, requires_approval);
+                        ledger.transfer(
+                            TransactionKind::Reserve,
+                            Some(withdrawal.id),
+                            &withdrawal.asset,
+                            Account::Available(user_email.clone()),
+                            Account::Reserved(user_email),
+                            amount,
+                        );
+                        if withdrawal.status == WithdrawalStatus::Approved {
+                            start_payout(&mut withdrawal, payouts.as_ref(), &db_tx);
+                        }
+
+                        withdrawals.push(withdrawal.clone());
+                        WithdrawalDbResponseType { status: withdrawal_status(&withdrawal), withdrawal: Some(withdrawal) }
+                    } else {
+                        WithdrawalDbResponseType { status: format!("Insufficient {} balance", asset), withdrawal: None }
+                    }
+                } else {
+                    WithdrawalDbResponseType { status: format!("User not found! User: {}", user_email), withdrawal: None }
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::ReviewWithdrawal { admin_email, withdrawal_id, approve, reason, response_status } => {
+                let response = if !withdrawal_policy.is_admin(&admin_email) {
+                    WithdrawalDbResponseType { status: "Only admins can review withdrawals".to_string(), withdrawal: None }
+                } else if let Some(withdrawal) = withdrawals.iter_mut().find(|w| w.id == withdrawal_id) {
+                    if !matches!(withdrawal.status, WithdrawalStatus::Pending | WithdrawalStatus::Approved) {
+                        WithdrawalDbResponseType { status: format!("Withdrawal is already {:?}", withdrawal.status), withdrawal: Some(withdrawal.clone()) }
+                    } else if withdrawal.payout_in_flight {
+                        WithdrawalDbResponseType { status: "Withdrawal payout is already in progress".to_string(), withdrawal: Some(withdrawal.clone()) }
+                    } else if let Some(user) = users.get_mut(&withdrawal.user_email) {
+                        withdrawal.reviewed_by = Some(admin_email);
+                        if approve {
+                            withdrawal.status = WithdrawalStatus::Approved;
+                            start_payout(withdrawal, payouts.as_ref(), &db_tx);
+                        } else {
+                            user.release(&withdrawal.asset, withdrawal.amount);
+                            ledger.transfer(
+                                TransactionKind::Release,
+                                Some(withdrawal.id),
+                                &withdrawal.asset,
+                                Account::Reserved(withdrawal.user_email.clone()),
+                                Account::Available(withdrawal.user_email.clone()),
+                                withdrawal.amount,
+                            );
+                            withdrawal.status = WithdrawalStatus::Rejected;
+                            withdrawal.reason = reason.or(Some("Rejected by an admin".to_string()));
+                        }
+                        WithdrawalDbResponseType { status: withdrawal_status(withdrawal), withdrawal: Some(withdrawal.clone()) }
+                    } else {
+                        WithdrawalDbResponseType { status: format!("User not found! User: {}", withdrawal.user_email), withdrawal: None }
+                    }
+                } else {
+                    WithdrawalDbResponseType { status: "Withdrawal not found".to_string(), withdrawal: None }
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::ListWithdrawals { user_email, response_status } => {
+                let response = if users.contains_key(&user_email) {
+                    ListWithdrawalsDbResponseType {
+                        status: "Success, withdrawals of the user".to_string(),
+                        withdrawals: withdrawals.iter().filter(|w| w.user_email == user_email).cloned().collect(),
+                    }
+                } else {
+                    ListWithdrawalsDbResponseType { status: format!("User not found! User: {}", user_email), withdrawals: vec![] }
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::PendingWithdrawals { admin_email, response_status } => {
+                let response = if withdrawal_policy.is_admin(&admin_email) {
+                    ListWithdrawalsDbResponseType {
+                        status: "Success, withdrawals waiting for approval".to_string(),
+                        withdrawals: withdrawals.iter().filter(|w| w.status == WithdrawalStatus::Pending).cloned().collect(),
+                    }
+                } else {
+                    ListWithdrawalsDbResponseType { status: "Only admins can review withdrawals".to_string(), withdrawals: vec![] }
+                };
+                let _ = response_status.send(response);
+            }
+            DbCommand::PayoutResult { withdrawal_id, result } => {
+                let Some(withdrawal) = withdrawals.iter_mut().find(|w| w.id == withdrawal_id && w.payout_in_flight) else {
+                    println!("Payout result for unknown withdrawal {}", withdrawal_id);
+                    continue;
+                };
+                match users.get_mut(&withdrawal.user_email) {
+                    Some(user) => finish_payout(withdrawal, user, &mut ledger, result),
+                    None => println!("Payout result for withdrawal {} of a missing user", withdrawal_id),
+                }
             }
         }
     }
 }
 
+/// Hands an approved withdrawal to the payout provider on a task of its own. The result comes
+/// back as a `PayoutResult` command, until then the withdrawal stays approved with its funds held.
+fn start_payout(withdrawal: &mut Withdrawal, payouts: &dyn PayoutProvider, db_tx: &mpsc::WeakSender<DbCommand>) {
+    withdrawal.payout_in_flight = true;
+    withdrawal.reason = None;
+
+    let withdrawal_id = withdrawal.id;
+    let payout = payouts.pay_out(withdrawal.clone());
+    let db_tx = db_tx.clone();
+    tokio::spawn(async move {
+        let result = payout.await;
+        if let Some(db_tx) = db_tx.upgrade() {
+            let _ = db_tx.send(DbCommand::PayoutResult { withdrawal_id, result }).await;
+        }
+    });
+}
+
+/// Applies what the payout provider answered. Once paid, the held funds leave the user's
+/// reserved balance for good. A failed payout keeps them held and the withdrawal approved.
+fn finish_payout(withdrawal: &mut Withdrawal, user: &mut User, ledger: &mut Ledger, result: Result<String, String>) {
+    withdrawal.payout_in_flight = false;
+    match result {
+        Ok(reference) => {
+            user.debit(&withdrawal.asset, withdrawal.amount, withdrawal.amount);
+            ledger.transfer(
+                TransactionKind::Withdrawal,
+                Some(withdrawal.id),
+                &withdrawal.asset,
+                Account::Reserved(withdrawal.user_email.clone()),
+                Account::External,
+                withdrawal.amount,
+            );
+            withdrawal.status = WithdrawalStatus::Completed;
+            withdrawal.payout_reference = Some(reference);
+            withdrawal.reason = None;
+        }
+        Err(reason) => withdrawal.reason = Some(format!("Payout failed, {}", reason)),
+    }
+}
+
+/// Status line for a withdrawal after it was requested or reviewed
+fn withdrawal_status(withdrawal: &Withdrawal) -> String {
+    match withdrawal.status {
+        WithdrawalStatus::Pending => "Success, withdrawal held and waiting for approval".to_string(),
+        WithdrawalStatus::Approved if withdrawal.payout_in_flight => "Success, withdrawal approved, payout in progress".to_string(),
+        WithdrawalStatus::Approved => format!(
+            "Success, withdrawal approved and held, {}",
+            withdrawal.reason.as_deref().unwrap_or("payout pending")
+        ),
+        WithdrawalStatus::Rejected => "Success, withdrawal rejected and funds released".to_string(),
+        WithdrawalStatus::Completed => "Success, withdrawal paid out".to_string(),
+    }
+}
+
+/// Pays `amount` of `pays` for `proceeds` of `receives`, drawing on the order's reservation
+/// before the available balance, and returns how much the reservation covered.
+/// Nothing changes when the two together cannot cover the payment.
+fn settle_leg(user: &mut User, reservation: &mut u64, pays: &str, amount: u64, receives: &str, proceeds: u64) -> Result<u64, String> {
+    let balance = user.balance(pays);
+    let from_reserved = (*reservation).min(amount).min(balance.reserved);
+    if amount - from_reserved > balance.available {
+        return Err(format!("Insufficient {} balance of {}", pays, user.email));
+    }
+
+    *reservation -= from_reserved;
+    user.debit(pays, amount, from_reserved);
+    user.credit(receives, proceeds);
+    Ok(from_reserved)
+}
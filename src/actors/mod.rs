@@ -2,5 +2,5 @@ pub mod db;
 pub mod orderbook;
 
 pub use db::{DbCommand, DbSender, start_db_actor};
-pub use orderbook::{OrderbookCommand, OrderbookResponse, start_orderbook_actor};
+pub use orderbook::{EventSender, OrderbookCommand, OrderbookEvent, OrderbookResponse, start_orderbook_actor};
 
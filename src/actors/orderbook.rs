@@ -1,13 +1,44 @@
 use std::collections::{BTreeMap, HashMap};
-use tokio::sync::{mpsc, oneshot};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use uuid::Uuid;
 
-use crate::actors::db::{DbCommand, DbSender};
-use crate::domain::{MarketBook, Order, Side};
+use crate::actors::db::{DbCommand, DbSender, ReconciliationDbResponseType, SettlementFailure};
+use crate::domain::{
+    AuctionKind, Clock, Market, MarketConfig, MatchOutcome, Order, OrderRejection, OrderState, Peg, PostOnly, PostOnlyOutcome, SelfTradePrevention, Side, StopOrder, TimeInForce,
+    Trade, TradingPhase, TrailBy, TrailingStop, Uncross, User,
+};
+
+/// How often the actor sweeps the books for expired good-til-time orders
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+pub type EventSender = broadcast::Sender<OrderbookEvent>;
+
+/// Things the orderbook actor publishes without being asked
+#[derive(Clone, Debug, Serialize)]
+pub enum OrderbookEvent {
+    OrderExpired {
+        market_id: u64,
+        order: Order,
+    },
+    /// Where a running auction would uncross, sent whenever that changes.
+    /// `None` once the book stops crossing.
+    IndicativeUncross {
+        market_id: u64,
+        uncross: Option<Uncross>,
+    },
+}
 
 pub enum OrderbookCommand {
     CreateMarket {
         market_id: u64,
+        /// Asset bought and sold
+        base_asset: String,
+        /// Asset prices are quoted in and paid with
+        quote_asset: String,
+        config: MarketConfig,
         resp: oneshot::Sender<OrderbookResponse>,
     },
     ListMarkets {
@@ -19,6 +50,17 @@ pub enum OrderbookCommand {
         side: Side,
         qty: u64,
         price: u64,
+        display_qty: Option<u64>,
+        expires_at: Option<u64>,
+        /// When set, `price` is ignored and derived from the book
+        peg: Option<Peg>,
+        time_in_force: TimeInForce,
+        post_only: Option<PostOnly>,
+        self_trade_prevention: Option<SelfTradePrevention>,
+        all_or_none: bool,
+        min_qty: Option<u64>,
+        /// Rest without showing in `GetBook`
+        hidden: bool,
         resp: oneshot::Sender<OrderbookResponse>,
     },
     NewMarketOrder {
@@ -26,60 +68,174 @@ pub enum OrderbookCommand {
         user_id: String,
         side: Side,
         qty: u64,
+        /// Never trade beyond this price
+        worst_price: Option<u64>,
+        /// Never trade further than this from the opposite best at entry, 100 = 1%
+        max_slippage_bps: Option<u64>,
+        self_trade_prevention: Option<SelfTradePrevention>,
+        all_or_none: bool,
+        min_qty: Option<u64>,
         resp: oneshot::Sender<OrderbookResponse>,
     },
-    CancelOrder {
+    NewStopOrder {
+        market_id: u64,
+        user_id: String,
+        side: Side,
+        qty: u64,
+        stop_price: u64,
+        limit_price: Option<u64>,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    NewTrailingStopOrder {
         market_id: u64,
+        user_id: String,
         side: Side,
+        qty: u64,
+        trail: TrailBy,
+        limit_offset: Option<u64>,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    CancelOrder {
+        market_id: u64,
+        user_id: String,
+        order_id: Uuid,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    AmendOrder {
+        market_id: u64,
+        user_id: String,
         order_id: Uuid,
+        new_price: Option<u64>,
+        /// New total working quantity, iceberg reserve included
+        new_qty: Option<u64>,
         resp: oneshot::Sender<OrderbookResponse>,
     },
     GetBook {
         market_id: u64,
         resp: oneshot::Sender<OrderbookResponse>,
     },
+    StartAuction {
+        market_id: u64,
+        kind: AuctionKind,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    EndAuction {
+        market_id: u64,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    /// Looks an order up by id in whichever market it was placed
+    GetOrder {
+        order_id: Uuid,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    /// Working orders of a user across every market, stops included
+    ListOpenOrders {
+        user_id: String,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
+    /// Cancels every working order of a user, optionally only in one market or on one side
+    CancelAll {
+        user_id: String,
+        market_id: Option<u64>,
+        side: Option<Side>,
+        resp: oneshot::Sender<OrderbookResponse>,
+    },
 }
 
 pub struct OrderbookResponse {
+    /// Whether the command did what was asked. Callers branch on this, `status` only describes
+    /// the outcome and can carry user-chosen text such as asset names and emails.
+    pub accepted: bool,
     pub status: String,
-    pub fills: Vec<crate::domain::Trade>,
+    pub order_id: Option<Uuid>,
+    pub fills: Vec<Trade>,
     pub remaining_qty: u64,
     pub bids: Option<BTreeMap<u64, std::collections::VecDeque<Order>>>,
     pub asks: Option<BTreeMap<u64, std::collections::VecDeque<Order>>>,
     pub market_ids: Option<Vec<u64>>,
     pub canceled: bool,
+    /// Orders cancelled by self-trade prevention while matching
+    pub canceled_orders: Vec<Uuid>,
+    pub post_only: Option<PostOnlyOutcome>,
+    pub phase: Option<TradingPhase>,
+    /// Indicative uncross for `GetBook`, the executed one for `EndAuction`
+    pub uncross: Option<Uncross>,
+    pub order_state: Option<OrderState>,
+    pub open_orders: Vec<OrderState>,
 }
 
 impl OrderbookResponse {
+    /// Response to a command that did what was asked
+    fn accepted(status: impl Into<String>) -> Self {
+        Self {
+            accepted: true,
+            ..Self::empty(status)
+        }
+    }
+
+    /// Response to a command that was turned down, or one that only reports `status`
     fn empty(status: impl Into<String>) -> Self {
         Self {
+            accepted: false,
             status: status.into(),
+            order_id: None,
             fills: vec![],
             remaining_qty: 0,
             bids: None,
             asks: None,
             market_ids: None,
             canceled: false,
+            canceled_orders: vec![],
+            post_only: None,
+            phase: None,
+            uncross: None,
+            order_state: None,
+            open_orders: vec![],
         }
     }
 }
 
-pub async fn start_orderbook_actor(mut rx: mpsc::Receiver<OrderbookCommand>, db_tx: DbSender) {
-    let mut order_book: HashMap<u64, MarketBook> = HashMap::new();
+pub async fn start_orderbook_actor(
+    mut rx: mpsc::Receiver<OrderbookCommand>,
+    db_tx: DbSender,
+    events_tx: EventSender,
+    clock: Arc<dyn Clock>,
+) {
+    let mut order_book: HashMap<u64, Market> = HashMap::new();
+    let mut expiry_timer = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
 
     println!("Orderbook actor started");
 
-    while let Some(cmd) = rx.recv().await {
+    loop {
+        let cmd = tokio::select! {
+            cmd = rx.recv() => match cmd {
+                Some(cmd) => cmd,
+                None => break,
+            },
+            _ = expiry_timer.tick() => {
+                expire_orders(&mut order_book, &events_tx, clock.now_millis());
+                run_market_timers(&mut order_book, &db_tx, clock.as_ref()).await;
+                reprice_pegged_orders(&mut order_book);
+                publish_indicative_uncross(&mut order_book, &events_tx);
+                release_closed_orders(&mut order_book, &db_tx).await;
+                continue;
+            }
+        };
+
         match cmd {
-            OrderbookCommand::CreateMarket { market_id, resp } => {
-                let response = if order_book.contains_key(&market_id) {
-                    OrderbookResponse::empty(format!("Market {} already exists", market_id))
-                } else {
-                    order_book.insert(market_id, MarketBook::new());
+            OrderbookCommand::CreateMarket { market_id, base_asset, quote_asset, config, resp } => {
+                let response = if let Err(reason) = config.validate() {
+                    OrderbookResponse::empty(reason)
+                } else if base_asset.is_empty() || quote_asset.is_empty() || base_asset == quote_asset {
+                    OrderbookResponse::empty("Base and quote assets must be two different assets")
+                } else if let std::collections::hash_map::Entry::Vacant(entry) = order_book.entry(market_id) {
+                    entry.insert(Market::new(market_id, base_asset, quote_asset, config));
                     OrderbookResponse {
                         market_ids: Some(order_book.keys().cloned().collect()),
-                        ..OrderbookResponse::empty(format!("Market {} created", market_id))
+                        ..OrderbookResponse::accepted(format!("Market {} created", market_id))
                     }
+                } else {
+                    OrderbookResponse::empty(format!("Market {} already exists", market_id))
                 };
                 let _ = resp.send(response);
             }
@@ -87,154 +243,995 @@ pub async fn start_orderbook_actor(mut rx: mpsc::Receiver<OrderbookCommand>, db_
                 let ids = order_book.keys().cloned().collect::<Vec<_>>();
                 let response = OrderbookResponse {
                     market_ids: Some(ids),
-                    ..OrderbookResponse::empty("Markets listed")
+                    ..OrderbookResponse::accepted("Markets listed")
                 };
                 let _ = resp.send(response);
             }
-            OrderbookCommand::NewLimitOrder { market_id, user_id, side, qty, price, resp } => {
-                let response = if let Some(book) = order_book.get_mut(&market_id) {
-                    let (oneshot_tx, oneshot_rx) = oneshot::channel();
-                    let _ = db_tx.send(DbCommand::GetUser {
-                        user_email: user_id.clone(),
-                        response_status: oneshot_tx,
-                    }).await;
-
-                    match oneshot_rx.await {
-                        Ok(response) => match response.user {
-                            Some(user) => {
-                                let response = match side {
-                                    Side::Bid if price * qty > user.balance => {
-                                        OrderbookResponse::empty("Insufficient balance")
-                                    }
-                                    Side::Ask if qty > user.holdings => {
-                                        OrderbookResponse::empty("Insufficient holdings")
-                                    }
-                                    _ => {
-                                        let order = Order::new(user_id.clone(), qty, price, side);
-                                        let (trades, remaining_order) = book.match_order(order);
-
-                                        let (tx, rx) = oneshot::channel();
-                                        let _ = db_tx.send(DbCommand::Reconciliation {
-                                            trades: trades.clone(),
-                                            response_status: tx,
-                                        }).await;
-                                        let _ = rx.await;
-
-                                        if let Some(order) = remaining_order {
-                                            book.insert_order(order);
-                                            OrderbookResponse {
-                                                status: "Success, resting remaining order".to_string(),
-                                                fills: trades,
-                                                remaining_qty: 0,
-                                                bids: None,
-                                                asks: None,
-                                                market_ids: None,
-                                                canceled: false,
-                                            }
-                                        } else {
-                                            OrderbookResponse {
-                                                status: "Success, fully matched".to_string(),
-                                                fills: trades,
-                                                remaining_qty: 0,
-                                                bids: None,
-                                                asks: None,
-                                                market_ids: None,
-                                                canceled: false,
-                                            }
-                                        }
-                                    }
-                                };
-                                response
-                            }
-                            None => OrderbookResponse::empty("User does not exist"),
-                        },
-                        Err(_) => OrderbookResponse::empty("Database error"),
-                    }
+            OrderbookCommand::NewLimitOrder { market_id, user_id, side, qty, price, display_qty, expires_at, peg, time_in_force, post_only, self_trade_prevention, all_or_none, min_qty, hidden, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    let order = Order {
+                        display_qty,
+                        self_trade_prevention,
+                        expires_at,
+                        peg,
+                        all_or_none,
+                        min_qty,
+                        hidden,
+                        ..Order::new(user_id, qty, price, side)
+                    };
+                    let submitted = OrderState::new(order.id, &order.user_id, order.side.clone(), qty, peg.is_none().then_some(price));
+                    let response = new_limit_order(market, &db_tx, order, time_in_force, post_only, clock.as_ref()).await;
+                    track_rejection(market, submitted, response)
                 } else {
                     OrderbookResponse::empty("Market does not exist")
                 };
 
                 let _ = resp.send(response);
             }
-            OrderbookCommand::NewMarketOrder { market_id, user_id, side , qty , resp } => {
-                let response = if let Some(book) = order_book.get_mut(&market_id) {
-                    let (oneshot_tx, oneshot_rx) = oneshot::channel();
-                    let _ = db_tx.send(DbCommand::GetUser { 
-                        user_email: user_id.clone(), 
-                        response_status: oneshot_tx 
-                    }).await;
-
-                    match oneshot_rx.await {
-                        Ok(response) => {
-                            match response.user {
-                                Some(user) => {
-                                    println!("User {} has balance {}", user.email, user.balance );
-                                    let order = Order::new(user_id.clone(), qty, 0, side);
-
-                                    let (trades, _remaining_order) = book.match_order(order);
-
-                                    let (tx, rx) = oneshot::channel();
-                                    let _ = db_tx.send(DbCommand::Reconciliation { trades: trades.clone(), response_status: tx }).await;
-                                    let _ = rx.await;
-
-                                    OrderbookResponse {
-                                        status: "Market order processed".to_string(),
-                                        fills: trades,
-                                        remaining_qty: 0,
-                                        bids: None,
-                                        asks: None,
-                                        market_ids: None,
-                                        canceled: false,
-                                    }
-                                }
-                                None => OrderbookResponse::empty("Error finding user"),
-                            }
-                        }
-                        Err(_) => OrderbookResponse::empty("Database error"),
-                    }
+            OrderbookCommand::NewMarketOrder { market_id, user_id, side , qty , worst_price, max_slippage_bps, self_trade_prevention, all_or_none, min_qty, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    let order = Order {
+                        self_trade_prevention,
+                        all_or_none,
+                        min_qty,
+                        ..Order::new(user_id, qty, 0, side)
+                    };
+                    let submitted = OrderState::new(order.id, &order.user_id, order.side.clone(), qty, None);
+                    let response = new_market_order(market, &db_tx, order, worst_price, max_slippage_bps, clock.as_ref()).await;
+                    track_rejection(market, submitted, response)
+                } else {
+                    OrderbookResponse::empty("Market does not exist")
+                };
+
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::NewStopOrder { market_id, user_id, side, qty, stop_price, limit_price, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    let stop = StopOrder::new(user_id, side, qty, stop_price, limit_price);
+                    let submitted = OrderState::new(stop.id, &stop.user_id, stop.side.clone(), qty, limit_price);
+                    let response = new_stop_order(market, &db_tx, stop).await;
+                    track_rejection(market, submitted, response)
+                } else {
+                    OrderbookResponse::empty("Market does not exist")
+                };
+
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::NewTrailingStopOrder { market_id, user_id, side, qty, trail, limit_offset, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    // the reference price is only known once the market has been checked
+                    let stop = TrailingStop::new(user_id, side, qty, trail, limit_offset, 0);
+                    let submitted = OrderState::new(stop.id, &stop.user_id, stop.side.clone(), qty, None);
+                    let response = new_trailing_stop_order(market, &db_tx, stop).await;
+                    track_rejection(market, submitted, response)
                 } else {
                     OrderbookResponse::empty("Market does not exist")
                 };
 
                 let _ = resp.send(response);
             }
-            OrderbookCommand::CancelOrder { market_id, side, order_id, resp } => {
-                let response = if let Some(book) = order_book.get_mut(&market_id) {
-                    let removed = book.cancel_order(side, order_id);
-                    if removed {
-                        OrderbookResponse {
-                            status: "Order canceled".to_string(),
-                            fills: vec![],
-                            remaining_qty: 0,
-                            bids: None,
-                            asks: None,
-                            market_ids: None,
-                            canceled: true,
+            OrderbookCommand::CancelOrder { market_id, user_id, order_id, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    let owner = market.book.get_order(order_id).map(|o| o.user_id.clone())
+                        .or_else(|| market.triggers.owner(order_id).map(str::to_string));
+
+                    match owner {
+                        Some(owner) if owner == user_id => {
+                            let removed = market.book.cancel_order(order_id).is_some() || market.triggers.cancel(order_id);
+                            if removed {
+                                market.orders.cancel(order_id);
+                            }
+                            OrderbookResponse {
+                                order_id: Some(order_id),
+                                canceled: removed,
+                                accepted: removed,
+                                ..OrderbookResponse::empty("Order canceled")
+                            }
                         }
-                    } else {
-                        OrderbookResponse::empty("Order not found")
+                        Some(_) => OrderbookResponse::empty("Order belongs to another user"),
+                        None => OrderbookResponse::empty("Order not found"),
                     }
                 } else {
                     OrderbookResponse::empty("Market does not exist")
                 };
                 let _ = resp.send(response);
             }
+            OrderbookCommand::AmendOrder { market_id, user_id, order_id, new_price, new_qty, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    amend_order(market, &db_tx, &user_id, order_id, new_price, new_qty, clock.as_ref()).await
+                } else {
+                    OrderbookResponse::empty("Market does not exist")
+                };
+                let _ = resp.send(response);
+            }
             OrderbookCommand::GetBook { market_id, resp } => {
-                let response = if let Some(book) = order_book.get(&market_id) {
+                let response = if let Some(market) = order_book.get(&market_id) {
                     OrderbookResponse {
-                        status: "Successful! Current order book snapshot".to_string(),
-                        fills: vec![],
-                        remaining_qty: 0,
-                        bids: Some(book.bids.clone()),
-                        asks: Some(book.asks.clone()),
-                        market_ids: None,
-                        canceled: false,
+                        bids: Some(market.book.snapshot(&Side::Bid)),
+                        asks: Some(market.book.snapshot(&Side::Ask)),
+                        phase: Some(market.phase),
+                        uncross: market.phase.is_auction().then(|| market.indicative_uncross()).flatten(),
+                        ..OrderbookResponse::accepted("Successful! Current order book snapshot")
                     }
                 } else {
                     OrderbookResponse::empty("Market does not exist")
                 };
                 let _ = resp.send(response);
             }
+            OrderbookCommand::StartAuction { market_id, kind, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    start_auction(market, kind)
+                } else {
+                    OrderbookResponse::empty("Market does not exist")
+                };
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::EndAuction { market_id, resp } => {
+                let response = if let Some(market) = order_book.get_mut(&market_id) {
+                    end_auction(market, &db_tx, clock.as_ref()).await
+                } else {
+                    OrderbookResponse::empty("Market does not exist")
+                };
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::GetOrder { order_id, resp } => {
+                let response = match order_book.values().find_map(|market| market.orders.get(order_id)) {
+                    Some(state) => OrderbookResponse {
+                        order_id: Some(order_id),
+                        order_state: Some(state.clone()),
+                        ..OrderbookResponse::accepted("Success, order found")
+                    },
+                    None => OrderbookResponse::empty("Order not found"),
+                };
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::ListOpenOrders { user_id, resp } => {
+                let mut open_orders: Vec<OrderState> = order_book
+                    .values()
+                    .flat_map(|market| market.orders.open_orders(&user_id, None))
+                    .cloned()
+                    .collect();
+                open_orders.sort_by_key(|state| (state.market_id, state.price));
+
+                let response = OrderbookResponse {
+                    open_orders,
+                    ..OrderbookResponse::accepted("Success, open orders listed")
+                };
+                let _ = resp.send(response);
+            }
+            OrderbookCommand::CancelAll { user_id, market_id, side, resp } => {
+                let response = if market_id.is_some_and(|market_id| !order_book.contains_key(&market_id)) {
+                    OrderbookResponse::empty("Market does not exist")
+                } else {
+                    let canceled_orders: Vec<Uuid> = order_book
+                        .values_mut()
+                        .filter(|market| market_id.is_none_or(|market_id| market.id == market_id))
+                        .flat_map(|market| cancel_user_orders(market, &user_id, side.as_ref()))
+                        .collect();
+
+                    OrderbookResponse {
+                        canceled: !canceled_orders.is_empty(),
+                        canceled_orders,
+                        ..OrderbookResponse::accepted("Success, orders canceled")
+                    }
+                };
+                let _ = resp.send(response);
+            }
+        }
+
+        // pegged orders follow whatever the command did to the top of the book
+        reprice_pegged_orders(&mut order_book);
+        publish_indicative_uncross(&mut order_book, &events_tx);
+        // funds of orders that ended go back once their fills are settled
+        release_closed_orders(&mut order_book, &db_tx).await;
+    }
+}
+
+/// Looks the user up in the DB actor, turning a miss into the response to send back
+async fn get_user(db_tx: &DbSender, user_id: &str) -> Result<User, OrderbookResponse> {
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    let _ = db_tx.send(DbCommand::GetUser {
+        user_email: user_id.to_string(),
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => response.user.ok_or_else(|| OrderbookResponse::empty("User does not exist")),
+        Err(_) => Err(OrderbookResponse::empty("Database error")),
+    }
+}
+
+/// What an order locks while open: the notional at its limit for a bid, the quantity for an ask
+fn required_funds(side: &Side, price: u64, qty: u64) -> u64 {
+    match side {
+        Side::Bid => price.saturating_mul(qty),
+        Side::Ask => qty,
+    }
+}
+
+/// Locks an order's funds in the asset its side pays with, turning a shortfall into the
+/// response to send back. An order that already holds a reservation has it replaced, what it held
+/// before comes back.
+async fn reserve(market: &Market, db_tx: &DbSender, order_id: Uuid, user_id: &str, side: &Side, amount: u64) -> Result<u64, OrderbookResponse> {
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+    let _ = db_tx.send(DbCommand::Reserve {
+        order_id,
+        user_email: user_id.to_string(),
+        asset: market.funding_asset(side).to_string(),
+        amount,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.reserved => Ok(response.held),
+        Ok(response) => Err(OrderbookResponse::empty(response.status)),
+        Err(_) => Err(OrderbookResponse::empty("Database error")),
+    }
+}
+
+/// Gives back what orders that filled, were cancelled or expired still hold
+async fn release_closed_orders(order_book: &mut HashMap<u64, Market>, db_tx: &DbSender) {
+    let order_ids: Vec<Uuid> = order_book.values_mut().flat_map(|market| market.orders.take_closed()).collect();
+    if !order_ids.is_empty() {
+        release(db_tx, order_ids).await;
+    }
+}
+
+/// Hands back whatever the orders still hold
+async fn release(db_tx: &DbSender, order_ids: Vec<Uuid>) {
+    let (tx, rx) = oneshot::channel();
+    let _ = db_tx.send(DbCommand::Release {
+        order_ids,
+        response_status: tx,
+    }).await;
+    let _ = rx.await;
+}
+
+/// Pulls expired good-til-time orders off every book and announces each one
+fn expire_orders(order_book: &mut HashMap<u64, Market>, events_tx: &EventSender, now: u64) {
+    for (market_id, market) in order_book.iter_mut() {
+        for order in market.book.remove_expired(now) {
+            market.orders.expire(order.id);
+            println!("Order {} expired in market {}", order.id, market_id);
+            let _ = events_tx.send(OrderbookEvent::OrderExpired {
+                market_id: *market_id,
+                order,
+            });
+        }
+    }
+}
+
+fn reprice_pegged_orders(order_book: &mut HashMap<u64, Market>) {
+    for market in order_book.values_mut() {
+        for order_id in market.book.reprice_pegged() {
+            if let Some(price) = market.book.get_order(order_id).map(|order| order.price) {
+                market.orders.reprice(order_id, price);
+            }
+        }
+    }
+}
+
+/// Pulls every working order `user_id` has in `market`, resting or waiting to trigger,
+/// only those on `side` when one is given. Returns the ids of the orders cancelled.
+fn cancel_user_orders(market: &mut Market, user_id: &str, side: Option<&Side>) -> Vec<Uuid> {
+    let order_ids: Vec<Uuid> = market.orders.open_orders(user_id, side).iter().map(|state| state.order_id).collect();
+
+    let mut canceled_orders = Vec::new();
+    for order_id in order_ids {
+        if market.book.cancel_order(order_id).is_some() || market.triggers.cancel(order_id) {
+            market.orders.cancel(order_id);
+            canceled_orders.push(order_id);
+        }
+    }
+
+    canceled_orders
+}
+
+/// Reopens markets whose circuit breaker halt is over and uncrosses the auctions they
+/// reopen through once those are due
+async fn run_market_timers(order_book: &mut HashMap<u64, Market>, db_tx: &DbSender, clock: &dyn Clock) {
+    let now = clock.now_millis();
+    for (market_id, market) in order_book.iter_mut() {
+        if market.reopen_if_due(now) {
+            println!("Market {} reopened after a circuit breaker halt", market_id);
+        }
+        if market.auction_ends_at.is_some_and(|ends_at| ends_at <= now) {
+            let response = end_auction(market, db_tx, clock).await;
+            println!("Market {} reopening auction ended: {}", market_id, response.status);
+        }
+    }
+}
+
+/// Publishes the indicative uncross of every market in auction whenever it has moved
+fn publish_indicative_uncross(order_book: &mut HashMap<u64, Market>, events_tx: &EventSender) {
+    for (market_id, market) in order_book.iter_mut() {
+        if !market.phase.is_auction() {
+            continue;
+        }
+
+        let uncross = market.indicative_uncross();
+        if uncross != market.indicative {
+            market.indicative = uncross.clone();
+            let _ = events_tx.send(OrderbookEvent::IndicativeUncross {
+                market_id: *market_id,
+                uncross,
+            });
+        }
+    }
+}
+
+/// Records a new order the flow turned away as `Rejected` and hands its id back with the
+/// response, so the rejection can be looked up like any other order
+fn track_rejection(market: &mut Market, submitted: OrderState, response: OrderbookResponse) -> OrderbookResponse {
+    if response.accepted {
+        return response;
+    }
+
+    let order_id = submitted.order_id;
+    market.orders.reject(submitted, &response.status);
+    OrderbookResponse {
+        order_id: Some(order_id),
+        ..response
+    }
+}
+
+fn order_rejected(rejection: OrderRejection) -> OrderbookResponse {
+    OrderbookResponse::empty(format!("Order rejected, {}", rejection))
+}
+
+/// Why an order's all-or-none or minimum quantity settings cannot work, if they cannot
+fn fill_constraint_error(order: &Order) -> Option<&'static str> {
+    match order.min_qty {
+        Some(0) => return Some("Minimum quantity must be greater than zero"),
+        Some(min_qty) if min_qty > order.qty => return Some("Minimum quantity cannot exceed the order quantity"),
+        Some(min_qty) if order.display_qty.is_some_and(|display_qty| min_qty > display_qty) => {
+            return Some("Minimum quantity cannot exceed the iceberg display quantity");
+        }
+        _ => {}
+    }
+
+    if order.all_or_none && order.display_qty.is_some() {
+        return Some("All-or-none orders cannot be icebergs");
+    }
+
+    None
+}
+
+/// Stamps `trades`, fires the stops they reach, feeds every print to the circuit breaker
+/// and the order tracker and settles them all with the DB actor. A market closed by its
+/// auction only moves its last price. On failure the market is left as matching made it,
+/// the caller rolls it back to its checkpoint.
+async fn settle(market: &mut Market, db_tx: &DbSender, trades: &mut [Trade], clock: &dyn Clock) -> Result<(), Option<SettlementFailure>> {
+    market.stamp_trades(trades, clock.now_nanos());
+    if market.phase == TradingPhase::Closed {
+        market.last_trade_price = trades.last().map(|t| t.price).or(market.last_trade_price);
+        market.orders.record_fills(trades);
+        return reconcile(market, db_tx, trades.to_vec()).await;
+    }
+
+    let mut triggered = market.fire_stops(trades);
+    market.stamp_trades(&mut triggered, clock.now_nanos());
+    let all_trades = [trades.to_vec(), triggered].concat();
+
+    market.orders.record_fills(&all_trades);
+    if market.record_trades(&all_trades, clock.now_millis()) {
+        println!("Circuit breaker tripped at {:?}, market halted", market.last_trade_price);
+    }
+    reconcile(market, db_tx, all_trades).await
+}
+
+/// Brings the order tracker and reservations in line with what self-trade prevention did while
+/// matching. Orders it cut down order that much less and a cut order still resting only holds
+/// what its remaining quantity needs, orders it pulled are cancelled.
+async fn track_self_trade_prevention(market: &mut Market, db_tx: &DbSender, canceled_orders: &[Uuid], decremented: &[(Uuid, u64)]) {
+    for (order_id, qty) in decremented {
+        market.orders.decrement(*order_id, *qty);
+        if let Some(order) = market.book.get_order(*order_id) {
+            let amount = required_funds(&order.side, order.price, order.total_qty());
+            let _ = reserve(market, db_tx, *order_id, &order.user_id, &order.side, amount).await;
+        }
+    }
+    for order_id in canceled_orders {
+        market.orders.cancel(*order_id);
+    }
+}
+
+/// Quantity self-trade prevention cut off `order_id` while it matched
+fn decremented_qty(decremented: &[(Uuid, u64)], order_id: Uuid) -> u64 {
+    decremented.iter().filter(|(id, _)| *id == order_id).map(|(_, qty)| qty).sum()
+}
+
+/// Settles trades with the DB actor, which applies all of them or none. The error is the leg
+/// that could not be paid for, `None` if the DB actor could not be reached.
+async fn reconcile(market: &Market, db_tx: &DbSender, trades: Vec<Trade>) -> Result<(), Option<SettlementFailure>> {
+    if trades.is_empty() {
+        return Ok(());
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let _ = db_tx.send(DbCommand::Reconciliation {
+        trades,
+        base_asset: market.base_asset.clone(),
+        quote_asset: market.quote_asset.clone(),
+        response_status: tx,
+    }).await;
+
+    match rx.await {
+        Ok(ReconciliationDbResponseType { outcome, .. }) => outcome.map(|_| ()).map_err(Some),
+        Err(_) => Err(None),
+    }
+}
+
+/// Runs `step`, the matching one command does, and settles the trades it makes. Everything
+/// `step` changed is rolled back when a leg cannot be paid for. A resting order or stop that
+/// could not pay for its fill, a re-pegged order or a stop-market that swept further than it
+/// locked funds for, is cancelled and `step` runs again without it. If the leg was `order_id`'s
+/// own, or the DB actor could not be reached, the error comes back instead.
+async fn match_and_settle(
+    market: &mut Market,
+    db_tx: &DbSender,
+    order_id: Option<Uuid>,
+    clock: &dyn Clock,
+    mut step: impl FnMut(&mut Market) -> MatchOutcome,
+) -> Result<MatchOutcome, OrderbookResponse> {
+    loop {
+        let checkpoint = market.checkpoint();
+        let mut outcome = step(market);
+        let Err(failure) = settle(market, db_tx, &mut outcome.trades, clock).await else {
+            market.commit();
+            return Ok(outcome);
+        };
+        market.rollback(checkpoint);
+
+        match failure {
+            Some(failure) if Some(failure.order_id) != order_id && cancel_unfunded(market, &failure) => {
+                println!("Order {} cancelled, {}", failure.order_id, failure.reason);
+            }
+            Some(failure) => {
+                return Err(OrderbookResponse::empty(format!("Trades could not be settled and were rolled back, {}", failure.reason)));
+            }
+            None => return Err(OrderbookResponse::empty("Database error")),
+        }
+    }
+}
+
+/// Pulls the order whose leg failed off the book or out of the trigger book and cancels it,
+/// its reservation goes back with the other closed orders. Returns `false` if it is in neither.
+fn cancel_unfunded(market: &mut Market, failure: &SettlementFailure) -> bool {
+    if market.book.cancel_order(failure.order_id).is_none() && !market.triggers.cancel(failure.order_id) {
+        return false;
+    }
+    market.orders.cancel_with_reason(failure.order_id, &format!("Could not pay for its fill, {}", failure.reason));
+    true
+}
+
+async fn new_limit_order(
+    market: &mut Market,
+    db_tx: &DbSender,
+    mut order: Order,
+    time_in_force: TimeInForce,
+    post_only: Option<PostOnly>,
+    clock: &dyn Clock,
+) -> OrderbookResponse {
+    if let Some(reason) = market.phase.closed_reason() {
+        return OrderbookResponse::empty(reason);
+    }
+    match market.phase {
+        TradingPhase::Auction(_) if time_in_force != TimeInForce::Gtc => {
+            return OrderbookResponse::empty("Only GTC orders are accepted during an auction");
+        }
+        TradingPhase::Auction(_) if post_only.is_some() => {
+            return OrderbookResponse::empty("Post-only orders are not accepted during an auction");
+        }
+        _ => {}
+    }
+
+    let user = match get_user(db_tx, &order.user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    order.self_trade_prevention = order.self_trade_prevention.or(user.self_trade_prevention);
+
+    if let Some(expires_at) = order.expires_at {
+        if time_in_force != TimeInForce::Gtc {
+            return OrderbookResponse::empty("Expiry only applies to orders that rest on the book");
+        }
+        if expires_at <= clock.now_millis() {
+            return OrderbookResponse::empty("Order expiry is already in the past");
+        }
+    }
+
+    if let Some(peg) = order.peg {
+        if time_in_force != TimeInForce::Gtc {
+            return OrderbookResponse::empty("Pegged orders must be GTC");
+        }
+        match market.book.peg_price(&peg, &order.side) {
+            Some(price) => order.price = price,
+            None => return OrderbookResponse::empty("No reference price to peg to, the book is empty"),
+        }
+    }
+
+    if let Err(rejection) = market.config.check_order(order.price, order.qty).and_then(|_| market.check_price_bands(order.price)) {
+        return order_rejected(rejection);
+    }
+
+    if order.display_qty == Some(0) {
+        return OrderbookResponse::empty("Iceberg display quantity must be greater than zero");
+    }
+    if order.hidden && order.display_qty.is_some() {
+        return OrderbookResponse::empty("Hidden orders cannot be icebergs, nothing of them is displayed");
+    }
+    if let Some(reason) = fill_constraint_error(&order) {
+        return OrderbookResponse::empty(reason);
+    }
+
+    // Post-only orders are settled against the book before any funds check,
+    // a repriced bid needs less balance than the one requested
+    let post_only = match post_only {
+        None => None,
+        Some(mode) => {
+            let rejected = |reason: &str| OrderbookResponse {
+                post_only: Some(PostOnlyOutcome::Rejected),
+                ..OrderbookResponse::empty(reason)
+            };
+
+            if time_in_force != TimeInForce::Gtc {
+                return rejected("Post-only orders must be GTC");
+            }
+
+            match (mode, market.book.passive_price(&order.side, order.price)) {
+                (_, Some(price)) if price == order.price => Some(PostOnlyOutcome::Accepted),
+                (PostOnly::Reprice, Some(price)) => {
+                    order.price = price;
+                    Some(PostOnlyOutcome::Repriced { price })
+                }
+                (PostOnly::Reject, Some(_)) => return rejected("Post-only order rejected, it would cross the book"),
+                (_, None) => return rejected("Post-only order rejected, no passive price available"),
+            }
+        }
+    };
+
+    let amount = required_funds(&order.side, order.price, order.qty);
+    if let Err(response) = reserve(market, db_tx, order.id, &order.user_id, &order.side, amount).await {
+        return response;
+    }
+
+    let order_id = order.id;
+    let order_qty = order.qty;
+    let state = OrderState::new(order_id, &order.user_id, order.side.clone(), order_qty, Some(order.price));
+
+    // orders build up without matching until the auction uncrosses
+    if market.phase.is_auction() {
+        market.orders.open(state);
+        market.book.insert_order(order);
+        return OrderbookResponse {
+            order_id: Some(order_id),
+            remaining_qty: order_qty,
+            ..OrderbookResponse::accepted("Success, order queued for the auction")
+        };
+    }
+
+    let matched = match_and_settle(market, db_tx, Some(order_id), clock, |market| {
+        market.orders.open(state.clone());
+        // FOK is decided up front so a killed order never touches the book. Every run decides
+        // it again, an order cancelled for not paying takes its liquidity with it.
+        if time_in_force == TimeInForce::Fok && !market.book.fills_completely(&order) {
+            market.orders.cancel(order_id);
+            return MatchOutcome { trades: vec![], remaining_order: Some(order.clone()), canceled_orders: vec![], decremented: vec![] };
+        }
+
+        let outcome = market.book.match_order(order.clone());
+        if let Some(remaining) = outcome.remaining_order.as_ref().filter(|_| time_in_force == TimeInForce::Gtc) {
+            market.book.insert_order(remaining.clone());
+        }
+        outcome
+    })
+    .await;
+    let MatchOutcome { trades, remaining_order, canceled_orders, decremented } = match matched {
+        Ok(outcome) => outcome,
+        Err(response) => return reject_unsettled(db_tx, order_id, response).await,
+    };
+    if time_in_force == TimeInForce::Fok && remaining_order.is_some() {
+        return OrderbookResponse {
+            order_id: Some(order_id),
+            remaining_qty: order_qty,
+            ..OrderbookResponse::empty("Order killed, FOK could not be fully filled")
+        };
+    }
+    let remaining_qty = order_qty - trades.iter().map(|t| t.qty).sum::<u64>() - decremented_qty(&decremented, order_id);
+
+    let status = match remaining_order {
+        None if canceled_orders.contains(&order_id) => "Success, remainder canceled by self-trade prevention",
+        Some(_) if time_in_force == TimeInForce::Ioc => "Success, unfilled remainder canceled (IOC)",
+        Some(_) => "Success, resting remaining order",
+        None => "Success, fully matched",
+    };
+
+    if time_in_force == TimeInForce::Ioc {
+        market.orders.cancel(order_id);
+    }
+    track_self_trade_prevention(market, db_tx, &canceled_orders, &decremented).await;
+
+    OrderbookResponse {
+        order_id: Some(order_id),
+        fills: trades,
+        remaining_qty,
+        canceled_orders,
+        post_only,
+        ..OrderbookResponse::accepted(status)
+    }
+}
+
+/// Sweeps the book up to an optional price bound. Whatever cannot fill inside the bound
+/// is cancelled and reported back as `remaining_qty`.
+async fn new_market_order(
+    market: &mut Market,
+    db_tx: &DbSender,
+    mut order: Order,
+    worst_price: Option<u64>,
+    max_slippage_bps: Option<u64>,
+    clock: &dyn Clock,
+) -> OrderbookResponse {
+    if let Some(reason) = market.phase.closed_reason() {
+        return OrderbookResponse::empty(reason);
+    }
+    if market.phase.is_auction() {
+        return OrderbookResponse::empty("Market orders are not accepted during an auction");
+    }
+
+    if let Err(rejection) = market.config.check_qty(order.qty) {
+        return order_rejected(rejection);
+    }
+    if let Some(reason) = fill_constraint_error(&order) {
+        return OrderbookResponse::empty(reason);
+    }
+
+    let user = match get_user(db_tx, &order.user_id).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    order.self_trade_prevention = order.self_trade_prevention.or(user.self_trade_prevention);
+
+    let best_opposite = match order.side {
+        Side::Bid => market.book.best_ask(),
+        Side::Ask => market.book.best_bid(),
+    };
+    let Some(best_opposite) = best_opposite else {
+        return OrderbookResponse {
+            remaining_qty: order.qty,
+            ..OrderbookResponse::empty("No liquidity on the opposite side")
+        };
+    };
+
+    // the tightest of the requested bounds, or the whole book when there are none
+    let slippage_price = max_slippage_bps.map(|bps| {
+        let slippage = u64::try_from(best_opposite as u128 * bps as u128 / 10_000).unwrap_or(u64::MAX);
+        match order.side {
+            Side::Bid => best_opposite.saturating_add(slippage),
+            Side::Ask => best_opposite.saturating_sub(slippage),
+        }
+    });
+    order.price = match order.side {
+        Side::Bid => [worst_price, slippage_price].into_iter().flatten().min().unwrap_or(u64::MAX),
+        Side::Ask => [worst_price, slippage_price].into_iter().flatten().max().unwrap_or(0),
+    };
+    // never sweep past the price bands
+    order.price = market.clamp_to_bands(&order.side, order.price);
+
+    let Ok(estimated_notional) = u64::try_from(market.book.estimate_fill(&order).1) else {
+        return order_rejected(OrderRejection::NotionalTooLarge);
+    };
+    if estimated_notional < market.config.min_notional {
+        return order_rejected(OrderRejection::BelowMinNotional { min_notional: market.config.min_notional });
+    }
+    // a bid locks what it is expected to pay, an ask the quantity it sells
+    let amount = match order.side {
+        Side::Bid => estimated_notional,
+        Side::Ask => order.qty,
+    };
+    if let Err(response) = reserve(market, db_tx, order.id, &order.user_id, &order.side, amount).await {
+        return response;
+    }
+
+    let order_id = order.id;
+    let order_qty = order.qty;
+    let has_min_fill = order.entry_min_fill().is_some();
+    let state = OrderState::new(order_id, &order.user_id, order.side.clone(), order_qty, None);
+    let matched = match_and_settle(market, db_tx, Some(order_id), clock, |market| {
+        market.orders.open(state.clone());
+        market.book.match_order(order.clone())
+    })
+    .await;
+    let MatchOutcome { trades, canceled_orders, decremented, .. } = match matched {
+        Ok(outcome) => outcome,
+        Err(response) => return reject_unsettled(db_tx, order_id, response).await,
+    };
+    let remaining_qty = order_qty - trades.iter().map(|t| t.qty).sum::<u64>();
+
+    let status = if remaining_qty == 0 {
+        "Success, market order fully filled"
+    } else if trades.is_empty() && has_min_fill {
+        "Success, minimum fill not available, market order canceled"
+    } else if canceled_orders.contains(&order_id) {
+        "Success, remainder canceled by self-trade prevention"
+    } else {
+        "Success, market order partially filled, unfilled quantity canceled"
+    };
+
+    // whatever a market order did not fill is gone
+    market.orders.cancel(order_id);
+    track_self_trade_prevention(market, db_tx, &canceled_orders, &decremented).await;
+
+    OrderbookResponse {
+        order_id: Some(order_id),
+        fills: trades,
+        remaining_qty,
+        canceled_orders,
+        ..OrderbookResponse::accepted(status)
+    }
+}
+
+async fn new_stop_order(market: &mut Market, db_tx: &DbSender, stop: StopOrder) -> OrderbookResponse {
+    if let Some(reason) = market.phase.closed_reason() {
+        return OrderbookResponse::empty(reason);
+    }
+
+    let checked = market.config.check_price(stop.stop_price)
+        .and_then(|_| market.config.check_order(stop.limit_price.unwrap_or(stop.stop_price), stop.qty));
+    if let Err(rejection) = checked {
+        return order_rejected(rejection);
+    }
+
+    if market.last_trade_price.is_some_and(|last| stop.is_triggered_by(last)) {
+        return OrderbookResponse::empty("Stop price already reached by the last trade");
+    }
+
+    // a stop-market buy locks funds at its stop price, the best guess of where it will fill
+    let amount = required_funds(&stop.side, stop.limit_price.unwrap_or(stop.stop_price), stop.qty);
+    if let Err(response) = reserve(market, db_tx, stop.id, &stop.user_id, &stop.side, amount).await {
+        return response;
+    }
+
+    let order_id = stop.id;
+    market.orders.open(OrderState::new(order_id, &stop.user_id, stop.side.clone(), stop.qty, stop.limit_price));
+    market.triggers.insert(stop);
+
+    OrderbookResponse {
+        order_id: Some(order_id),
+        ..OrderbookResponse::accepted("Success, stop order accepted")
+    }
+}
+
+/// Places a trailing stop. Its reference price is set here, from the market's last trade.
+async fn new_trailing_stop_order(market: &mut Market, db_tx: &DbSender, mut stop: TrailingStop) -> OrderbookResponse {
+    if let Some(reason) = market.phase.closed_reason() {
+        return OrderbookResponse::empty(reason);
+    }
+
+    if let Err(rejection) = market.config.check_qty(stop.qty) {
+        return order_rejected(rejection);
+    }
+    // basis-point trails move with the price, only fixed distances have to sit on the tick
+    let tick_size = market.config.tick_size;
+    if matches!(stop.trail, TrailBy::Amount(amount) if !amount.is_multiple_of(tick_size)) || stop.limit_offset.is_some_and(|offset| !offset.is_multiple_of(tick_size)) {
+        return order_rejected(OrderRejection::OffTick { tick_size });
+    }
+    if matches!(stop.trail, TrailBy::BasisPoints(bps) if bps > 10_000) {
+        return OrderbookResponse::empty("Trail cannot be more than 10000 basis points");
+    }
+
+    // the trail starts from the last trade, there is nothing to follow before the first one
+    let Some(reference_price) = market.last_trade_price else {
+        return OrderbookResponse::empty("No trades yet, a trailing stop needs a last trade price to follow");
+    };
+
+    stop.reference_price = reference_price;
+    let distance = stop.trail.distance(reference_price);
+    if distance == 0 || (stop.side == Side::Ask && distance >= reference_price) {
+        return OrderbookResponse::empty("Trail distance must be greater than zero and below the last trade price");
+    }
+
+    // a buy trail only ever moves its stop down, the price at entry is the most it can pay
+    let Some(entry_price) = stop.stop_price().checked_add(stop.limit_offset.unwrap_or(0)) else {
+        return OrderbookResponse::empty("Trail and limit offset put the price out of range");
+    };
+    let amount = required_funds(&stop.side, entry_price, stop.qty);
+    if let Err(response) = reserve(market, db_tx, stop.id, &stop.user_id, &stop.side, amount).await {
+        return response;
+    }
+
+    let order_id = stop.id;
+    market.orders.open(OrderState::new(order_id, &stop.user_id, stop.side.clone(), stop.qty, None));
+    market.triggers.insert_trailing(stop);
+
+    OrderbookResponse {
+        order_id: Some(order_id),
+        ..OrderbookResponse::accepted("Success, trailing stop order accepted")
+    }
+}
+
+/// Cancel/replace in one step. A pure size reduction keeps the order's queue position,
+/// a new price or a bigger size sends it back through matching and to the back of its level.
+async fn amend_order(
+    market: &mut Market,
+    db_tx: &DbSender,
+    user_id: &str,
+    order_id: Uuid,
+    new_price: Option<u64>,
+    new_qty: Option<u64>,
+    clock: &dyn Clock,
+) -> OrderbookResponse {
+    if let Some(reason) = market.phase.closed_reason() {
+        return OrderbookResponse::empty(reason);
+    }
+
+    let Some(resting) = market.book.get_order(order_id).cloned() else {
+        return OrderbookResponse::empty("Order not found");
+    };
+    if resting.user_id != user_id {
+        return OrderbookResponse::empty("Order belongs to another user");
+    }
+
+    if resting.peg.is_some() && new_price.is_some() {
+        return OrderbookResponse::empty("Pegged orders follow the book, only their quantity can be amended");
+    }
+
+    let price = new_price.unwrap_or(resting.price);
+    let qty = new_qty.unwrap_or(resting.total_qty());
+    if qty == 0 {
+        return OrderbookResponse::empty("Amended quantity must be greater than zero, cancel the order instead");
+    }
+    if price == resting.price && qty == resting.total_qty() {
+        return OrderbookResponse::empty("Nothing to amend");
+    }
+    if let Err(rejection) = market.config.check_order(price, qty) {
+        return order_rejected(rejection);
+    }
+    if price != resting.price && let Err(rejection) = market.check_price_bands(price) {
+        return order_rejected(rejection);
+    }
+
+    // the order's reservation is swapped for what the amended order needs
+    let held = match reserve(market, db_tx, order_id, user_id, &resting.side, required_funds(&resting.side, price, qty)).await {
+        Ok(held) => held,
+        Err(response) => return response,
+    };
+
+    if price == resting.price && qty < resting.total_qty() {
+        market.book.reduce_order(order_id, qty);
+        market.orders.amend(order_id, price, qty);
+        return OrderbookResponse {
+            order_id: Some(order_id),
+            remaining_qty: qty,
+            ..OrderbookResponse::accepted("Success, order reduced in place")
+        };
+    }
+
+    let side = resting.side.clone();
+    let order = Order {
+        qty,
+        price,
+        reserve_qty: 0,
+        ..resting
+    };
+
+    if market.phase.is_auction() {
+        market.book.cancel_order(order_id);
+        market.orders.amend(order_id, price, qty);
+        market.book.insert_order(order);
+        return OrderbookResponse {
+            order_id: Some(order_id),
+            remaining_qty: qty,
+            ..OrderbookResponse::accepted("Success, order amended and requeued for the auction")
+        };
+    }
+
+    let matched = match_and_settle(market, db_tx, Some(order_id), clock, |market| {
+        market.book.cancel_order(order_id);
+        market.orders.amend(order_id, price, qty);
+        let outcome = market.book.match_order(order.clone());
+        if let Some(remaining) = &outcome.remaining_order {
+            market.book.insert_order(remaining.clone());
+        }
+        outcome
+    })
+    .await;
+    let MatchOutcome { trades, remaining_order, canceled_orders, decremented } = match matched {
+        Ok(outcome) => outcome,
+        Err(response) => {
+            // the order rests as it did, so it gets its old reservation back
+            let _ = reserve(market, db_tx, order_id, user_id, &side, held).await;
+            return OrderbookResponse { order_id: Some(order_id), ..response };
         }
+    };
+    let remaining_qty = qty - trades.iter().map(|t| t.qty).sum::<u64>() - decremented_qty(&decremented, order_id);
+
+    let status = match remaining_order {
+        None if canceled_orders.contains(&order_id) => "Success, remainder canceled by self-trade prevention",
+        Some(_) => "Success, order amended and requeued",
+        None => "Success, amended order fully matched",
+    };
+
+    track_self_trade_prevention(market, db_tx, &canceled_orders, &decremented).await;
+
+    OrderbookResponse {
+        order_id: Some(order_id),
+        fills: trades,
+        remaining_qty,
+        canceled_orders,
+        ..OrderbookResponse::accepted(status)
+    }
+}
+
+/// Hands back the reservation of a new order whose trades could not be settled. The order
+/// ends up `Rejected` with the settlement failure as its reason.
+async fn reject_unsettled(db_tx: &DbSender, order_id: Uuid, response: OrderbookResponse) -> OrderbookResponse {
+    release(db_tx, vec![order_id]).await;
+    OrderbookResponse { order_id: Some(order_id), ..response }
+}
+
+fn start_auction(market: &mut Market, kind: AuctionKind) -> OrderbookResponse {
+    match (market.phase, kind) {
+        (TradingPhase::Auction(_), _) => return OrderbookResponse::empty("An auction is already running"),
+        (TradingPhase::Halted { .. }, _) => return OrderbookResponse::empty("Market is halted by the circuit breaker"),
+        (TradingPhase::Closed, AuctionKind::Closing) => return OrderbookResponse::empty("Market is closed, only an opening auction can start"),
+        _ => {}
+    }
+
+    market.phase = TradingPhase::Auction(kind);
+    market.indicative = None;
+
+    OrderbookResponse {
+        phase: Some(market.phase),
+        ..OrderbookResponse::accepted(format!("Success, {:?} auction started", kind))
+    }
+}
+
+/// Uncrosses the book at a single price and moves on to continuous trading after an
+/// opening auction or to closed after a closing one. Stops only fire once trading reopens.
+async fn end_auction(market: &mut Market, db_tx: &DbSender, clock: &dyn Clock) -> OrderbookResponse {
+    let TradingPhase::Auction(kind) = market.phase else {
+        return OrderbookResponse::empty("No auction is running");
+    };
+
+    // the auction keeps running if the uncross cannot be settled
+    let mut uncross = None;
+    let matched = match_and_settle(market, db_tx, None, clock, |market| {
+        uncross = market.indicative_uncross();
+        let outcome = match &uncross {
+            Some(uncross) => market.book.uncross(uncross.price),
+            None => MatchOutcome { trades: vec![], remaining_order: None, canceled_orders: vec![], decremented: vec![] },
+        };
+
+        market.indicative = None;
+        market.auction_ends_at = None;
+        market.phase = match kind {
+            AuctionKind::Opening => TradingPhase::Continuous,
+            AuctionKind::Closing => TradingPhase::Closed,
+        };
+        if let Some(uncross) = &uncross {
+            market.static_reference_price = Some(uncross.price);
+        }
+        outcome
+    })
+    .await;
+    let MatchOutcome { trades, canceled_orders, decremented, .. } = match matched {
+        Ok(outcome) => outcome,
+        Err(response) => return response,
+    };
+    track_self_trade_prevention(market, db_tx, &canceled_orders, &decremented).await;
+
+    let status = match &uncross {
+        Some(uncross) => format!("Success, auction uncrossed at {} for {}", uncross.price, uncross.volume),
+        None => "Success, auction ended without a cross".to_string(),
+    };
+
+    OrderbookResponse {
+        fills: trades,
+        canceled_orders,
+        phase: Some(market.phase),
+        uncross,
+        ..OrderbookResponse::accepted(status)
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
-use axum::{routing::post, Router};
+use axum::{routing::{get, post}, Router};
 use crate::app::AppState;
-use crate::handlers::{auth, market, orders};
+use crate::handlers::{auth, ledger, market, orders, withdrawals};
 
 pub fn create_router() -> Router<AppState> {
     Router::new()
@@ -8,11 +8,26 @@ pub fn create_router() -> Router<AppState> {
         .route("/signup", post(auth::signup_handler))
         .route("/signin", post(auth::signin_handler))
         .route("/onramp", post(auth::onramp_handler))
+        .route("/selftradeprevention", post(auth::self_trade_prevention_handler))
+        .route("/ledger", post(ledger::ledger_handler))
+        .route("/ledger/check", get(ledger::ledger_check_handler))
+        .route("/withdraw", post(withdrawals::withdraw_handler))
+        .route("/withdrawals", post(withdrawals::withdrawals_handler))
+        .route("/withdrawals/pending", post(withdrawals::pending_withdrawals_handler))
+        .route("/withdrawals/review", post(withdrawals::review_withdrawal_handler))
         .route("/createLimitOrder", post(orders::create_limit_order_handler))
         .route("/getorderbook", post(market::get_order_book_handler))
         .route("/createMarketOrder", post(orders::create_market_order_handler))
+        .route("/createStopOrder", post(orders::create_stop_order_handler))
+        .route("/createTrailingStopOrder", post(orders::create_trailing_stop_order_handler))
         .route("/cancelorder", post(orders::cancel_order_handler))
+        .route("/amendorder", post(orders::amend_order_handler))
+        .route("/orders/{id}", get(orders::get_order_handler))
+        .route("/openorders", post(orders::open_orders_handler))
+        .route("/cancelall", post(orders::cancel_all_handler))
         .route("/createmarket", post(market::create_market_handler))
         .route("/listmarkets", post(market::list_markets_handler))
+        .route("/startauction", post(market::start_auction_handler))
+        .route("/endauction", post(market::end_auction_handler))
 }
 
@@ -1,20 +1,24 @@
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
 use crate::app::{AppState, create_router};
-use crate::actors::{start_db_actor, start_orderbook_actor, DbCommand, OrderbookCommand};
+use crate::actors::{start_db_actor, start_orderbook_actor, DbCommand, OrderbookCommand, OrderbookEvent};
+use crate::domain::{StubPayoutProvider, SystemClock, WithdrawalPolicy};
 
 pub async fn run() {
     //? Starting the database actor
     let (db_tx, db_rx) = mpsc::channel::<DbCommand>(32);
-    tokio::spawn(start_db_actor(db_rx));
+    tokio::spawn(start_db_actor(db_rx, db_tx.clone(), withdrawal_policy_from_env(), Arc::new(StubPayoutProvider::default())));
 
     // Starting the orderbook actor
     let (ob_tx, ob_rx) = mpsc::channel::<OrderbookCommand>(32);
-    tokio::spawn(start_orderbook_actor(ob_rx, db_tx.clone()));
+    let (events_tx, _) = broadcast::channel::<OrderbookEvent>(256);
+    tokio::spawn(start_orderbook_actor(ob_rx, db_tx.clone(), events_tx.clone(), Arc::new(SystemClock)));
 
     //Main state's of the Application for data trasnder between the 2 threads
     let state = AppState {
         db_tx: db_tx.clone(),
         ob_tx: ob_tx.clone(),
+        events_tx,
     };
 
     // Create router
@@ -31,3 +35,25 @@ pub async fn run() {
         .expect("Server failed to start");
 }
 
+/// Admins come from `ADMIN_EMAILS` (comma separated) and the amounts paid out without them from
+/// `WITHDRAWAL_APPROVAL_THRESHOLDS` (`ASSET=amount`, comma separated). Withdrawals of an asset
+/// without a threshold always wait for an admin.
+fn withdrawal_policy_from_env() -> WithdrawalPolicy {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+
+    let admins = var("ADMIN_EMAILS")
+        .split(',')
+        .map(str::trim)
+        .filter(|email| !email.is_empty())
+        .map(str::to_string)
+        .collect();
+    let approval_thresholds = var("WITHDRAWAL_APPROVAL_THRESHOLDS")
+        .split(',')
+        .filter_map(|pair| {
+            let (asset, amount) = pair.split_once('=')?;
+            Some((asset.trim().to_string(), amount.trim().parse().ok()?))
+        })
+        .collect();
+
+    WithdrawalPolicy { admins, approval_thresholds }
+}
@@ -1,8 +1,10 @@
-use crate::actors::{DbSender, OrderbookCommand};
+use crate::actors::{DbSender, EventSender, OrderbookCommand};
 use tokio::sync::mpsc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_tx: DbSender,
     pub ob_tx: mpsc::Sender<OrderbookCommand>,
+    /// Subscribe here for events the orderbook actor publishes (e.g. expiries)
+    pub events_tx: EventSender,
 }
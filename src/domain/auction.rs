@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Order, Side};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AuctionKind {
+    /// Ends into continuous trading
+    Opening,
+    /// Ends with the market closed
+    Closing,
+}
+
+/// What a market does with incoming orders
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TradingPhase {
+    /// Orders match as they arrive
+    #[default]
+    Continuous,
+    /// Orders rest without matching until the auction uncrosses
+    Auction(AuctionKind),
+    /// No new orders, resting ones can still be cancelled
+    Closed,
+    /// Stopped by the circuit breaker until `until` (unix milliseconds), behaves like `Closed`
+    Halted { until: u64 },
+}
+
+impl TradingPhase {
+    pub fn is_auction(&self) -> bool {
+        matches!(self, TradingPhase::Auction(_))
+    }
+
+    /// Why no new orders are taken right now, `None` while they are
+    pub fn closed_reason(&self) -> Option<&'static str> {
+        match self {
+            TradingPhase::Closed => Some("Market is closed"),
+            TradingPhase::Halted { .. } => Some("Market is halted by the circuit breaker"),
+            _ => None,
+        }
+    }
+}
+
+/// Single price an auction uncrosses at and what trades there
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct Uncross {
+    pub price: u64,
+    pub volume: u64,
+    /// Quantity left unmatched on the heavier side at `price`
+    pub imbalance: u64,
+    /// Side with the surplus, `None` when both sides match exactly
+    pub imbalance_side: Option<Side>,
+}
+
+/// Picks the uncross price for a crossed book. The price that executes the most volume wins,
+/// ties go to the smallest imbalance, then to market pressure (the highest price if every
+/// candidate has surplus demand, the lowest if every one has surplus supply) and finally to
+/// the price closest to `reference_price`, or to the middle of the candidates without one.
+/// `None` when nothing crosses.
+pub fn discover_uncross(
+    bids: &BTreeMap<u64, VecDeque<Order>>,
+    asks: &BTreeMap<u64, VecDeque<Order>>,
+    reference_price: Option<u64>,
+) -> Option<Uncross> {
+    let level_qty = |orders: &VecDeque<Order>| orders.iter().map(Order::total_qty).sum::<u64>();
+    let prices: BTreeSet<u64> = bids.keys().chain(asks.keys()).copied().collect();
+
+    // best candidates so far, in ascending price order
+    let mut candidates: Vec<Uncross> = Vec::new();
+    for price in prices {
+        let demand: u64 = bids.range(price..).map(|(_, orders)| level_qty(orders)).sum();
+        let supply: u64 = asks.range(..=price).map(|(_, orders)| level_qty(orders)).sum();
+        let volume = demand.min(supply);
+        if volume == 0 {
+            continue;
+        }
+
+        let candidate = Uncross {
+            price,
+            volume,
+            imbalance: demand.abs_diff(supply),
+            imbalance_side: match demand.cmp(&supply) {
+                Ordering::Greater => Some(Side::Bid),
+                Ordering::Less => Some(Side::Ask),
+                Ordering::Equal => None,
+            },
+        };
+
+        let rank = |u: &Uncross| (u.volume, std::cmp::Reverse(u.imbalance));
+        match candidates.first().map(|best| rank(&candidate).cmp(&rank(best))) {
+            None | Some(Ordering::Greater) => candidates = vec![candidate],
+            Some(Ordering::Equal) => candidates.push(candidate),
+            Some(Ordering::Less) => {}
+        }
+    }
+
+    if candidates.iter().all(|c| c.imbalance_side == Some(Side::Bid)) {
+        return candidates.pop();
+    }
+    if candidates.iter().all(|c| c.imbalance_side == Some(Side::Ask)) {
+        return candidates.into_iter().next();
+    }
+
+    let lowest = candidates.first()?.price;
+    let highest = candidates.last()?.price;
+    let anchor = reference_price.unwrap_or(lowest + (highest - lowest) / 2);
+    candidates.into_iter().min_by_key(|c| (c.price.abs_diff(anchor), c.price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One order of `qty` per price
+    fn levels(side: Side, levels: &[(u64, u64)]) -> BTreeMap<u64, VecDeque<Order>> {
+        levels
+            .iter()
+            .map(|(price, qty)| (*price, VecDeque::from([Order::new("user".to_string(), *qty, *price, side.clone())])))
+            .collect()
+    }
+
+    fn uncross(bids: &[(u64, u64)], asks: &[(u64, u64)], reference_price: Option<u64>) -> Option<Uncross> {
+        discover_uncross(&levels(Side::Bid, bids), &levels(Side::Ask, asks), reference_price)
+    }
+
+    #[test]
+    fn most_volume_wins_over_a_smaller_imbalance() {
+        // 10 trade at 100 with 10 bid left over, only 9 at 101 with 3 offered left over
+        let best = uncross(&[(100, 11), (101, 9)], &[(100, 10), (101, 2)], None).unwrap();
+        assert_eq!(best, Uncross { price: 100, volume: 10, imbalance: 10, imbalance_side: Some(Side::Bid) });
+    }
+
+    #[test]
+    fn equal_volume_goes_to_the_smallest_imbalance() {
+        let best = uncross(&[(101, 10)], &[(100, 10), (101, 5)], None).unwrap();
+        assert_eq!(best, Uncross { price: 100, volume: 10, imbalance: 0, imbalance_side: None });
+    }
+
+    #[test]
+    fn equal_imbalance_goes_with_market_pressure() {
+        // surplus demand everywhere takes the highest price, surplus supply the lowest
+        let best = uncross(&[(102, 10)], &[(100, 6)], Some(100)).unwrap();
+        assert_eq!((best.price, best.imbalance_side), (102, Some(Side::Bid)));
+        let best = uncross(&[(102, 6)], &[(100, 10)], Some(102)).unwrap();
+        assert_eq!((best.price, best.imbalance_side), (100, Some(Side::Ask)));
+    }
+
+    #[test]
+    fn balanced_candidates_go_to_the_price_nearest_the_reference() {
+        let (bids, asks) = ([(104, 10)], [(100, 10)]);
+        assert_eq!(uncross(&bids, &asks, Some(103)).unwrap().price, 104);
+        assert_eq!(uncross(&bids, &asks, Some(90)).unwrap().price, 100);
+        // without one the middle of the candidates is the reference, and a tie takes the lower price
+        assert_eq!(uncross(&bids, &asks, None).unwrap().price, 100);
+    }
+
+    #[test]
+    fn nothing_crosses() {
+        assert_eq!(uncross(&[(99, 10)], &[(100, 10)], None), None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::domain::User;
+
+/// Where funds sit. User accounts hold what the exchange owes its users,
+/// `External` is the other side of everything that came in or went out.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Account {
+    /// Free funds of a user
+    Available(String),
+    /// Funds of a user locked behind open orders
+    Reserved(String),
+    /// The outside world funds are deposited from and paid out to
+    External,
+}
+
+impl Account {
+    pub fn owner(&self) -> Option<&str> {
+        match self {
+            Account::Available(owner) | Account::Reserved(owner) => Some(owner),
+            Account::External => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+/// One leg of a transaction
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    pub account: Account,
+    pub asset: String,
+    pub side: EntrySide,
+    pub amount: u64,
+}
+
+/// What caused a transaction
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum TransactionKind {
+    OnRamp,
+    Reserve,
+    Release,
+    Trade,
+    /// Funds paid out of the exchange
+    Withdrawal,
+}
+
+/// A balanced set of entries, for every asset the debits add up to the credits
+#[derive(Clone, Debug, Serialize)]
+pub struct Transaction {
+    pub id: u64,
+    pub kind: TransactionKind,
+    /// Order or trade the transaction belongs to
+    pub reference: Option<Uuid>,
+    pub entries: Vec<Entry>,
+}
+
+/// How one asset adds up across the exchange
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct AssetTotals {
+    /// Sum of every user's available and reserved balance
+    pub held_by_users: u64,
+    /// Net amount that came in from outside according to the ledger, withdrawals taken off
+    pub deposited: i128,
+    pub conserved: bool,
+}
+
+/// Outcome of checking the user balances against the ledger
+#[derive(Clone, Debug, Serialize)]
+pub struct ConservationReport {
+    pub conserved: bool,
+    pub assets: BTreeMap<String, AssetTotals>,
+    /// Users whose balances differ from what their ledger accounts say
+    pub mismatched_users: Vec<String>,
+}
+
+/// Append-only double-entry journal of every balance change
+#[derive(Default)]
+pub struct Ledger {
+    transactions: Vec<Transaction>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a transaction, dropping zero entries. Refused when the debits and
+    /// credits of any asset do not add up.
+    pub fn post(&mut self, kind: TransactionKind, reference: Option<Uuid>, entries: Vec<Entry>) -> Result<u64, &'static str> {
+        let entries: Vec<Entry> = entries.into_iter().filter(|entry| entry.amount > 0).collect();
+
+        let mut net: BTreeMap<&str, i128> = BTreeMap::new();
+        for entry in &entries {
+            *net.entry(&entry.asset).or_default() += signed(entry);
+        }
+        if net.values().any(|total| *total != 0) {
+            return Err("Unbalanced transaction, debits and credits differ");
+        }
+
+        let id = self.transactions.len() as u64 + 1;
+        self.transactions.push(Transaction { id, kind, reference, entries });
+        Ok(id)
+    }
+
+    /// Moves `amount` of `asset` from one account to another, which always balances
+    pub fn transfer(&mut self, kind: TransactionKind, reference: Option<Uuid>, asset: &str, from: Account, to: Account, amount: u64) {
+        let _ = self.post(kind, reference, vec![debit(from, asset, amount), credit(to, asset, amount)]);
+    }
+
+    /// Transactions touching `user`, each cut down to the user's own entries
+    pub fn user_transactions(&self, user: &str) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter_map(|transaction| {
+                let entries: Vec<Entry> = transaction
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.account.owner() == Some(user))
+                    .cloned()
+                    .collect();
+                (!entries.is_empty()).then(|| Transaction { entries, ..transaction.clone() })
+            })
+            .collect()
+    }
+
+    /// Credits minus debits of every account, per asset
+    pub fn balances(&self) -> BTreeMap<(Account, String), i128> {
+        let mut balances = BTreeMap::new();
+        for entry in self.transactions.iter().flat_map(|transaction| &transaction.entries) {
+            *balances.entry((entry.account.clone(), entry.asset.clone())).or_default() += signed(entry);
+        }
+        balances
+    }
+
+    /// Checks that, for every asset, what users hold is exactly what came in from outside,
+    /// and that every user's balances match their ledger accounts
+    pub fn check_conservation<'a>(&self, users: impl Iterator<Item = &'a User>) -> ConservationReport {
+        let ledger_balances = self.balances();
+        let ledger_balance = |account: Account, asset: &str| ledger_balances.get(&(account, asset.to_string())).copied().unwrap_or(0);
+
+        let mut assets: BTreeMap<String, AssetTotals> = BTreeMap::new();
+        let mut mismatched_users = Vec::new();
+
+        for user in users {
+            let mut matches = true;
+            for (asset, balance) in &user.balances {
+                let totals = assets.entry(asset.clone()).or_insert(AssetTotals { held_by_users: 0, deposited: 0, conserved: true });
+                totals.held_by_users += balance.available + balance.reserved;
+
+                matches &= ledger_balance(Account::Available(user.email.clone()), asset) == balance.available as i128
+                    && ledger_balance(Account::Reserved(user.email.clone()), asset) == balance.reserved as i128;
+            }
+            if !matches {
+                mismatched_users.push(user.email.clone());
+            }
+        }
+
+        for (asset, totals) in assets.iter_mut() {
+            totals.deposited = -ledger_balance(Account::External, asset);
+            totals.conserved = totals.held_by_users as i128 == totals.deposited;
+        }
+
+        ConservationReport {
+            conserved: mismatched_users.is_empty() && assets.values().all(|totals| totals.conserved),
+            assets,
+            mismatched_users,
+        }
+    }
+}
+
+pub fn debit(account: Account, asset: &str, amount: u64) -> Entry {
+    Entry { account, asset: asset.to_string(), side: EntrySide::Debit, amount }
+}
+
+pub fn credit(account: Account, asset: &str, amount: u64) -> Entry {
+    Entry { account, asset: asset.to_string(), side: EntrySide::Credit, amount }
+}
+
+fn signed(entry: &Entry) -> i128 {
+    match entry.side {
+        EntrySide::Credit => entry.amount as i128,
+        EntrySide::Debit => -(entry.amount as i128),
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use crate::domain::{
+    AuctionKind, MarketBook, MarketConfig, MatchOutcome, Order, OrderRejection, OrderTracker, Reopen, Side, StopOrder, Trade, TradingPhase,
+    TriggerBook, Uncross,
+};
+
+/// Market fields matching can change outside the book, trigger book and order tracker,
+/// as they were when `Market::checkpoint` was taken. Those three keep their own undo logs.
+pub struct Checkpoint {
+    last_trade_price: Option<u64>,
+    phase: TradingPhase,
+    indicative: Option<Uncross>,
+    auction_ends_at: Option<u64>,
+    static_reference_price: Option<u64>,
+    recent_prices: VecDeque<(u64, u64)>,
+    last_sequence: u64,
+}
+
+/// Everything the orderbook actor keeps per market: the resting book,
+/// the conditional orders waiting on it, the last price it traded at, its trading rules and phase.
+pub struct Market {
+    pub id: u64,
+    /// Asset the market trades, what asks sell and bids receive
+    pub base_asset: String,
+    /// Asset prices are in, what bids pay with and asks receive
+    pub quote_asset: String,
+    pub book: MarketBook,
+    pub triggers: TriggerBook,
+    /// Status of every order placed in the market, including those no longer on the book
+    pub orders: OrderTracker,
+    pub last_trade_price: Option<u64>,
+    pub config: MarketConfig,
+    pub phase: TradingPhase,
+    /// Indicative uncross last published for the running auction
+    pub indicative: Option<Uncross>,
+    /// When an auction started by the market itself uncrosses, unix milliseconds
+    pub auction_ends_at: Option<u64>,
+    /// Price the static band is measured from: the last auction or reopening price,
+    /// or the first trade of a market that never had one
+    pub static_reference_price: Option<u64>,
+    /// Trade prices with the time they printed, kept as far back as the circuit breaker looks
+    recent_prices: VecDeque<(u64, u64)>,
+    /// Sequence number of the last trade printed
+    last_sequence: u64,
+}
+
+impl Market {
+    pub fn new(id: u64, base_asset: String, quote_asset: String, config: MarketConfig) -> Self {
+        Self {
+            id,
+            base_asset,
+            quote_asset,
+            book: MarketBook::with_config(&config),
+            config,
+            triggers: TriggerBook::new(),
+            orders: OrderTracker::new(id),
+            last_trade_price: None,
+            phase: TradingPhase::Continuous,
+            indicative: None,
+            auction_ends_at: None,
+            static_reference_price: None,
+            recent_prices: VecDeque::new(),
+            last_sequence: 0,
+        }
+    }
+
+    /// Starts recording what matching changes, so the market can be put back with `rollback`
+    /// when its trades cannot be settled. The book, trigger book and order tracker each open an
+    /// undo log that keeps the first state of whatever they change, the rest of the market is
+    /// copied into the checkpoint. Every checkpoint ends in `commit`, which drops the logs and
+    /// keeps the changes, or `rollback`. Checkpoints do not nest, and either call is a no-op
+    /// on a part with no log open.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.book.begin_undo();
+        self.triggers.begin_undo();
+        self.orders.begin_undo();
+
+        Checkpoint {
+            last_trade_price: self.last_trade_price,
+            phase: self.phase,
+            indicative: self.indicative.clone(),
+            auction_ends_at: self.auction_ends_at,
+            static_reference_price: self.static_reference_price,
+            recent_prices: self.recent_prices.clone(),
+            last_sequence: self.last_sequence,
+        }
+    }
+
+    /// Keeps everything changed since the last checkpoint
+    pub fn commit(&mut self) {
+        self.book.commit();
+        self.triggers.commit();
+        self.orders.commit();
+    }
+
+    /// Undoes everything changed since `checkpoint` was taken
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.book.rollback();
+        self.triggers.rollback();
+        self.orders.rollback();
+
+        self.last_trade_price = checkpoint.last_trade_price;
+        self.phase = checkpoint.phase;
+        self.indicative = checkpoint.indicative;
+        self.auction_ends_at = checkpoint.auction_ends_at;
+        self.static_reference_price = checkpoint.static_reference_price;
+        self.recent_prices = checkpoint.recent_prices;
+        self.last_sequence = checkpoint.last_sequence;
+    }
+
+    /// Asset an order on `side` pays with and locks while open
+    pub fn funding_asset(&self, side: &Side) -> &str {
+        match side {
+            Side::Bid => &self.quote_asset,
+            Side::Ask => &self.base_asset,
+        }
+    }
+
+    /// Gives fresh trades the market id, their place in the market's sequence and the time they printed
+    pub fn stamp_trades(&mut self, trades: &mut [Trade], timestamp_nanos: u64) {
+        for trade in trades {
+            self.last_sequence += 1;
+            trade.market_id = self.id;
+            trade.sequence = self.last_sequence;
+            trade.timestamp_nanos = timestamp_nanos;
+        }
+    }
+
+    /// Rejects a limit price outside the static or dynamic band
+    pub fn check_price_bands(&self, price: u64) -> Result<(), OrderRejection> {
+        if let Some((low, high)) = self.static_band().filter(|(low, high)| price < *low || price > *high) {
+            return Err(OrderRejection::OutsideStaticBand { low, high });
+        }
+        if let Some((low, high)) = self.dynamic_band().filter(|(low, high)| price < *low || price > *high) {
+            return Err(OrderRejection::OutsideDynamicBand { low, high });
+        }
+        Ok(())
+    }
+
+    /// Pulls the price limit of an order that sweeps the book back inside the bands
+    pub fn clamp_to_bands(&self, side: &Side, price: u64) -> u64 {
+        [self.static_band(), self.dynamic_band()]
+            .into_iter()
+            .flatten()
+            .fold(price, |price, (low, high)| match side {
+                Side::Bid => price.min(high),
+                Side::Ask => price.max(low),
+            })
+    }
+
+    fn static_band(&self) -> Option<(u64, u64)> {
+        Some(band(self.static_reference_price?, self.config.static_band_bps?))
+    }
+
+    fn dynamic_band(&self) -> Option<(u64, u64)> {
+        Some(band(self.last_trade_price?, self.config.dynamic_band_bps?))
+    }
+
+    /// Feeds trades printed at `now` to the circuit breaker. Halts a continuously trading
+    /// market when the last price has moved too far from any price inside the window, and
+    /// returns whether it did.
+    pub fn record_trades(&mut self, trades: &[Trade], now: u64) -> bool {
+        if let Some(first) = trades.first() {
+            self.static_reference_price.get_or_insert(first.price);
+        }
+
+        let Some(breaker) = self.config.circuit_breaker else {
+            return false;
+        };
+
+        self.recent_prices.extend(trades.iter().map(|t| (now, t.price)));
+        while self.recent_prices.front().is_some_and(|(at, _)| at.saturating_add(breaker.window_ms) < now) {
+            self.recent_prices.pop_front();
+        }
+
+        let Some(&(_, last)) = self.recent_prices.back() else {
+            return false;
+        };
+        let moved = self
+            .recent_prices
+            .iter()
+            .any(|(_, price)| last.abs_diff(*price) as u128 * 10_000 > *price as u128 * breaker.move_bps as u128);
+
+        if moved && self.phase == TradingPhase::Continuous {
+            self.phase = TradingPhase::Halted { until: now.saturating_add(breaker.halt_ms) };
+            self.recent_prices.clear();
+            return true;
+        }
+
+        false
+    }
+
+    /// Ends a circuit breaker halt that is over, into continuous trading or an opening auction.
+    /// The static band is re-centred on the last trade. Returns whether the market reopened.
+    pub fn reopen_if_due(&mut self, now: u64) -> bool {
+        let TradingPhase::Halted { until } = self.phase else {
+            return false;
+        };
+        if now < until {
+            return false;
+        }
+
+        self.static_reference_price = self.last_trade_price;
+        match self.config.circuit_breaker.map(|breaker| breaker.reopen).unwrap_or_default() {
+            Reopen::Continuous => self.phase = TradingPhase::Continuous,
+            Reopen::Auction { duration_ms } => {
+                self.phase = TradingPhase::Auction(AuctionKind::Opening);
+                self.auction_ends_at = Some(now.saturating_add(duration_ms));
+                self.indicative = None;
+            }
+        }
+
+        true
+    }
+
+    /// Where the running auction would uncross right now, referenced to the last trade price
+    pub fn indicative_uncross(&self) -> Option<Uncross> {
+        self.book.indicative_uncross(self.last_trade_price)
+    }
+
+    /// Walks `trades` in order, updating the last price and firing every stop each price reaches.
+    /// Fills of a fired stop are fed back in, so one stop can set off the next.
+    /// Returns only the trades produced by fired stops.
+    pub fn fire_stops(&mut self, trades: &[Trade]) -> Vec<Trade> {
+        let mut fired_trades = Vec::new();
+        let mut prices: VecDeque<u64> = trades.iter().map(|t| t.price).collect();
+
+        while let Some(price) = prices.pop_front() {
+            self.last_trade_price = Some(price);
+
+            for stop in self.triggers.take_triggered(price) {
+                let (trades, remaining_order) = self.execute_stop(stop);
+                prices.extend(trades.iter().map(|t| t.price));
+                fired_trades.extend(trades);
+
+                if let Some(order) = remaining_order {
+                    self.book.insert_order(order);
+                }
+            }
+        }
+
+        fired_trades
+    }
+
+    /// Runs a fired stop against the book. A stop-limit hands back its unfilled
+    /// remainder to rest, whatever a stop-market could not fill is dropped and the stop cancelled.
+    fn execute_stop(&mut self, stop: StopOrder) -> (Vec<Trade>, Option<Order>) {
+        let is_limit = stop.limit_price.is_some();
+        let mut order = stop.into_order();
+        let order_id = order.id;
+        order.price = self.clamp_to_bands(&order.side, order.price);
+        let MatchOutcome { trades, remaining_order, canceled_orders, decremented } = self.book.match_order(order);
+
+        for (id, qty) in decremented {
+            self.orders.decrement(id, qty);
+        }
+        for id in canceled_orders {
+            self.orders.cancel(id);
+        }
+        if remaining_order.is_some() && !is_limit {
+            self.orders.cancel(order_id);
+        }
+
+        (trades, remaining_order.filter(|_| is_limit))
+    }
+}
+
+/// Lowest and highest price within `bps` of `reference`
+fn band(reference: u64, bps: u64) -> (u64, u64) {
+    let width = u64::try_from(reference as u128 * bps as u128 / 10_000).unwrap_or(u64::MAX);
+    (reference.saturating_sub(width), reference.saturating_add(width))
+}
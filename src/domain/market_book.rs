@@ -1,186 +1,631 @@
-use std::collections::{BTreeMap, VecDeque};
-use crate::domain::{Order, OrderSummary, Side, order, trade};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::domain::{discover_uncross, MarketConfig, MatchingPolicy, Order, Peg, PegReference, SelfTradePrevention, Side, Uncross};
 use crate::domain::Trade;
 
+/// Result of running an incoming order against the book
+pub struct MatchOutcome {
+    pub trades: Vec<Trade>,
+    /// Unfilled part of the incoming order, `None` if it filled or was cancelled
+    pub remaining_order: Option<Order>,
+    /// Orders cancelled by self-trade prevention, resting ones and possibly the incoming one
+    pub canceled_orders: Vec<Uuid>,
+    /// Quantity decrement-and-cancel took off each order it cut, resting and incoming alike
+    pub decremented: Vec<(Uuid, u64)>,
+}
+
+/// Price levels as they were before their first change since `begin_undo`, `None` for a level
+/// that did not exist
+type SavedLevels = HashMap<(Side, u64), Option<VecDeque<Order>>>;
+
 pub struct MarketBook {
     pub bids: BTreeMap<u64, VecDeque<Order>>,
     pub asks: BTreeMap<u64, VecDeque<Order>>,
+    /// Where every resting order lives, so lookups and cancels skip the level scan
+    order_index: HashMap<Uuid, (Side, u64)>,
+    /// Good-til-time orders by expiry. Entries are not removed on fill or cancel,
+    /// `remove_expired` just skips ids that are no longer resting.
+    expiries: BTreeMap<u64, Vec<Uuid>>,
+    /// Resting pegged orders in the order they were placed
+    pegged_orders: Vec<Uuid>,
+    /// Best un-pegged bid and ask the pegged orders were last priced against
+    peg_reference: (Option<u64>, Option<u64>),
+    /// How a level's quantity is shared out when an incoming order crosses it
+    policy: Arc<dyn MatchingPolicy>,
+    /// Step between prices, used wherever the book picks a price itself
+    tick_size: u64,
+    /// Levels to put back on `rollback`, recorded only between `begin_undo` and `commit`
+    undo: Option<SavedLevels>,
+}
+
+impl Default for MarketBook {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MarketBook {
     pub fn new() -> Self {
+        Self::with_config(&MarketConfig::default())
+    }
+
+    pub fn with_config(config: &MarketConfig) -> Self {
         Self {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            order_index: HashMap::new(),
+            expiries: BTreeMap::new(),
+            pegged_orders: Vec::new(),
+            peg_reference: (None, None),
+            policy: config.matching_policy.build(),
+            tick_size: config.tick_size,
+            undo: None,
+        }
+    }
+
+    /// Opens an undo log of every level as it was before its first change, see `Market::checkpoint`
+    pub fn begin_undo(&mut self) {
+        self.undo = Some(HashMap::new());
+    }
+
+    pub fn commit(&mut self) {
+        self.undo = None;
+    }
+
+    /// Expiry and peg entries of orders that came and went are left behind, both lists skip
+    /// ids that are not resting.
+    pub fn rollback(&mut self) {
+        let Some(saved) = self.undo.take() else {
+            return;
+        };
+
+        for (side, price) in saved.keys() {
+            let book_side = match side {
+                Side::Bid => &self.bids,
+                Side::Ask => &self.asks,
+            };
+            for order in book_side.get(price).into_iter().flatten() {
+                self.order_index.remove(&order.id);
+            }
+        }
+
+        for ((side, price), level) in saved {
+            let book_side = match side {
+                Side::Bid => &mut self.bids,
+                Side::Ask => &mut self.asks,
+            };
+            match level {
+                Some(orders) => {
+                    for order in &orders {
+                        self.order_index.insert(order.id, (side.clone(), price));
+                    }
+                    book_side.insert(price, orders);
+                }
+                None => {
+                    book_side.remove(&price);
+                }
+            }
         }
     }
 
     pub fn insert_order(&mut self, order: Order) {
+        self.place_order(order, false);
+    }
+
+    /// Puts an order taken off the book back at the front of its level, keeping its time priority
+    fn restore_order(&mut self, order: Order) {
+        self.place_order(order, true);
+    }
+
+    fn place_order(&mut self, mut order: Order, at_front: bool) {
+        order.show_display_slice();
+        self.order_index.insert(order.id, (order.side.clone(), order.price));
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.entry(expires_at).or_default().push(order.id);
+        }
+        if order.peg.is_some() && !self.pegged_orders.contains(&order.id) {
+            self.pegged_orders.push(order.id);
+        }
+
         let target_side = match order.side {
             Side::Bid => &mut self.bids,
             Side::Ask => &mut self.asks
         };
 
+        save_level(&mut self.undo, &order.side, order.price, target_side.get(&order.price));
         let entry = target_side.entry(order.price).or_insert_with(VecDeque::new);
-        entry.push_back(order);
+        let first_hidden = entry.iter().position(|o| o.hidden).unwrap_or(entry.len());
+        let position = match (order.hidden, at_front) {
+            (false, true) => 0,
+            (false, false) | (true, true) => first_hidden,
+            (true, false) => entry.len(),
+        };
+        entry.insert(position, order);
     }
 
-    pub fn match_order(&mut self , incoming_order: Order) -> (Vec<Trade>, Option<Order>){
-        
-        let mut fills = Vec::new();
-        let mut remaining_qty = incoming_order.qty;
+    /// Copy of one side of the book for `GetBook`, showing only what the market may see.
+    /// Hidden orders are left out, and so are levels holding nothing else.
+    pub fn snapshot(&self, side: &Side) -> BTreeMap<u64, VecDeque<Order>> {
+        let book_side = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
 
-        match incoming_order.side {
-            
-            Side::Bid => {
-                 //first lets get the whole arrray of all the availaible ask prices 
-                let ask_prices: Vec<u64> = self.asks.keys().cloned().collect();
-                
+        book_side
+            .iter()
+            .map(|(price, orders)| (*price, orders.iter().filter(|o| !o.hidden).map(Order::public_view).collect::<VecDeque<_>>()))
+            .filter(|(_, orders)| !orders.is_empty())
+            .collect()
+    }
 
-                // now lets first iterate over the array to get the best price to match the order
-                for ask_price in ask_prices {
+    pub fn best_bid(&self) -> Option<u64> {
+        self.bids.keys().next_back().copied()
+    }
 
-                    // check if qty is already 0 or price is too high 
-                    if remaining_qty == 0 || ask_price > incoming_order.price {
-                        break;
-                    }
+    pub fn best_ask(&self) -> Option<u64> {
+        self.asks.keys().next().copied()
+    }
 
-                    //getting all the availaible orders at this price 
-                    if let Some(ask_orders) = self.asks.get_mut(&ask_price){
-                        
-                        while let Some(mut best_ask_order) = ask_orders.pop_front(){
-                            
-                            if remaining_qty == 0 {
-                                // Put back the order we just popped
-                                ask_orders.push_front(best_ask_order);
-                                break;
-                            }
-
-                            let trade_qty = remaining_qty.min(best_ask_order.qty);
-
-                            let trade = Trade{
-                                id: uuid::Uuid::new_v4(),
-                                buyer: incoming_order.user_id.clone(),
-                                seller: best_ask_order.user_id.clone(),
-                                qty: trade_qty,
-                                price: ask_price
-                            };
-                            fills.push(trade);
-
-                            remaining_qty -= trade_qty;
-                            best_ask_order.qty -= trade_qty;
-
-                            if best_ask_order.qty > 0 {
-                                ask_orders.push_front(best_ask_order);
-                                break; // Move to next price level
-                            }
-
-                        }
-
-                        if ask_orders.is_empty() {
-                            self.asks.remove(&ask_price);
-                        }
-                    }
+    /// Closest price to `price` at which an order on `side` rests without crossing the opposite best.
+    /// `None` when no such price exists, e.g. a bid against an ask sitting at the minimum tick.
+    pub fn passive_price(&self, side: &Side, price: u64) -> Option<u64> {
+        match side {
+            Side::Bid => match self.best_ask() {
+                Some(ask) if ask <= price => ask.checked_sub(self.tick_size).filter(|p| *p > 0),
+                _ => Some(price),
+            },
+            Side::Ask => match self.best_bid() {
+                Some(bid) if bid >= price => bid.checked_add(self.tick_size),
+                _ => Some(price),
+            },
+        }
+    }
+
+    /// Best price on `side` set by an ordinary displayed order. Pegged orders are skipped so
+    /// they never chase each other, hidden ones so pegs never give them away.
+    fn unpegged_best(&self, side: &Side) -> Option<u64> {
+        let has_unpegged = |orders: &VecDeque<Order>| orders.iter().any(|o| o.peg.is_none() && !o.hidden);
+        match side {
+            Side::Bid => self.bids.iter().rev().find(|(_, orders)| has_unpegged(orders)).map(|(price, _)| *price),
+            Side::Ask => self.asks.iter().find(|(_, orders)| has_unpegged(orders)).map(|(price, _)| *price),
+        }
+    }
+
+    /// Where an order on `side` pegged with `peg` should be priced right now, rounded onto the
+    /// tick away from the opposite side. `None` while the reference side of the book is empty.
+    pub fn peg_price(&self, peg: &Peg, side: &Side) -> Option<u64> {
+        let reference = match peg.reference {
+            PegReference::BestBid => self.unpegged_best(&Side::Bid)?,
+            PegReference::BestAsk => self.unpegged_best(&Side::Ask)?,
+            PegReference::Mid => {
+                // summed in u128 so two prices near the top of the range cannot overflow
+                let sum = u128::from(self.unpegged_best(&Side::Bid)?) + u128::from(self.unpegged_best(&Side::Ask)?);
+                let mid = match side {
+                    Side::Bid => sum / 2,
+                    Side::Ask => sum.div_ceil(2),
+                };
+                u64::try_from(mid).ok()?
+            }
+        };
+
+        let price = u64::try_from((reference as i128 + peg.offset as i128).max(self.tick_size as i128)).ok()?;
+        match side {
+            Side::Bid => Some(price - price % self.tick_size),
+            Side::Ask => price.div_ceil(self.tick_size).checked_mul(self.tick_size),
+        }
+    }
+
+    /// Moves pegged orders to follow the top of the book once it has changed. A re-pegged order
+    /// joins the back of its new level, one whose price did not change keeps its place.
+    /// Re-pegging never crosses the book, the price stops one tick short of the opposite best.
+    /// Returns the ids of the orders that moved.
+    pub fn reprice_pegged(&mut self) -> Vec<Uuid> {
+        let reference = (self.unpegged_best(&Side::Bid), self.unpegged_best(&Side::Ask));
+        if reference == self.peg_reference {
+            return vec![];
+        }
+        self.peg_reference = reference;
+
+        self.pegged_orders.retain(|order_id| self.order_index.contains_key(order_id));
+
+        let mut repriced = Vec::new();
+        for order_id in self.pegged_orders.clone() {
+            let Some((peg, side, price)) = self.get_order(order_id).and_then(|o| Some((o.peg?, o.side.clone(), o.price))) else {
+                continue;
+            };
+            let target = self.peg_price(&peg, &side).and_then(|p| self.passive_price(&side, p));
+
+            if let Some(target) = target.filter(|target| *target != price)
+                && let Some(mut order) = self.cancel_order(order_id)
+            {
+                order.price = target;
+                self.insert_order(order);
+                repriced.push(order_id);
+            }
+        }
+
+        repriced
+    }
+
+    /// Whether `order` would fill its whole quantity right now. Matched for real and rolled back,
+    /// so self-trade prevention and the matching policy decide it exactly as they would match it.
+    /// Used to decide fill-or-kill orders before `match_order` keeps anything.
+    pub fn fills_completely(&mut self, order: &Order) -> bool {
+        let (outcome, _) = self.try_execute(order.clone(), |_| false);
+        outcome.trades.iter().map(|t| t.qty).sum::<u64>() == order.qty
+    }
+
+    /// Quantity and notional `order` would fill walking the book from the best price, without
+    /// touching it. Used to check funds for market orders before they sweep. The notional is
+    /// wide enough that any quantity at any price fits.
+    pub fn estimate_fill(&self, order: &Order) -> (u64, u128) {
+        let crossing_levels: Vec<(&u64, &VecDeque<Order>)> = match order.side {
+            Side::Bid => self.asks.range(..=order.price).collect(),
+            Side::Ask => self.bids.range(order.price..).rev().collect(),
+        };
+
+        let mut filled_qty = 0;
+        let mut notional = 0;
+        for (price, orders) in crossing_levels {
+            // the user's own orders never fill it; walking past them to worse prices can only
+            // overstate what the order pays, whatever self-trade prevention does with them
+            for resting in orders.iter().filter(|o| o.user_id != order.user_id) {
+                let qty = resting.total_qty().min(order.qty - filled_qty);
+                if qty < resting.resting_min_fill() {
+                    continue;
                 }
+                filled_qty += qty;
+                notional += qty as u128 * *price as u128;
 
+                if filled_qty == order.qty {
+                    return (filled_qty, notional);
+                }
             }
+        }
+
+        (filled_qty, notional)
+    }
 
-            Side::Ask => {
-                
-                //get the array of all the prices availaible in reversed order ie highest first
-                let bid_prices: Vec<u64> = self.bids.keys().rev().cloned().collect();
+    /// Runs `incoming_order` against the opposite side. An all-or-none or minimum-quantity
+    /// order is rolled back unless it fills enough, and then comes back untouched as the
+    /// remaining order.
+    pub fn match_order(&mut self, incoming_order: Order) -> MatchOutcome {
+        let Some(min_fill) = incoming_order.entry_min_fill() else {
+            return self.execute_order(incoming_order);
+        };
 
-                for bid_price in bid_prices {
+        let (outcome, kept) =
+            self.try_execute(incoming_order.clone(), |outcome| outcome.trades.iter().map(|t| t.qty).sum::<u64>() >= min_fill);
+        if kept {
+            return outcome;
+        }
 
-                    if remaining_qty == 0 || bid_price < incoming_order.price{
-                        break;
-                    }
+        MatchOutcome {
+            trades: vec![],
+            remaining_order: Some(incoming_order),
+            canceled_orders: vec![],
+            decremented: vec![],
+        }
+    }
 
-                    if let Some(bid_orders) = self.bids.get_mut(&bid_price){
+    /// Matches `order` under an undo log of its own and keeps the result only if `keep` accepts
+    /// it, otherwise the book is put back. Returns the outcome and whether it was kept. Levels the
+    /// trial changed join the enclosing log, if one is open, so its rollback still reaches them.
+    fn try_execute(&mut self, order: Order, keep: impl FnOnce(&MatchOutcome) -> bool) -> (MatchOutcome, bool) {
+        let outer = self.undo.replace(HashMap::new());
+        let outcome = self.execute_order(order);
+
+        if !keep(&outcome) {
+            self.rollback();
+            self.undo = outer;
+            return (outcome, false);
+        }
+
+        let trial = self.undo.take().unwrap_or_default();
+        self.undo = outer.map(|mut saved| {
+            for (level, orders) in trial {
+                saved.entry(level).or_insert(orders);
+            }
+            saved
+        });
+        (outcome, true)
+    }
+
+    /// Matches best price first. Within a level the market's matching policy decides who fills,
+    /// leaving out resting orders whose all-or-none or minimum quantity the share cannot meet.
+    /// Orders are then filled in queue order and the policy is asked again whenever the level
+    /// changes under it: an iceberg refilled its slice at the back, or self-trade prevention
+    /// pulled an order.
+    fn execute_order(&mut self, incoming_order: Order) -> MatchOutcome {
+        let mut fills = Vec::new();
+        let mut canceled_orders = Vec::new();
+        let mut decremented = Vec::new();
+        let mut remaining_qty = incoming_order.qty;
+        let self_trade_prevention = incoming_order.self_trade_prevention.unwrap_or_default();
 
-                        while let Some(mut bid_order) = bid_orders.pop_front(){
+        let crossing_prices: Vec<u64> = match incoming_order.side {
+            Side::Bid => self.asks.range(..=incoming_order.price).map(|(price, _)| *price).collect(),
+            Side::Ask => self.bids.range(incoming_order.price..).rev().map(|(price, _)| *price).collect(),
+        };
+        let (resting_side, book_side) = match incoming_order.side {
+            Side::Bid => (Side::Ask, &mut self.asks),
+            Side::Ask => (Side::Bid, &mut self.bids),
+        };
 
-                            if remaining_qty == 0 {
-                                bid_orders.push_front(bid_order);
-                                break;
-                            }
+        for level_price in crossing_prices {
+            if remaining_qty == 0 {
+                break;
+            }
+            save_level(&mut self.undo, &resting_side, level_price, book_side.get(&level_price));
+            let Some(level) = book_side.get_mut(&level_price) else {
+                continue;
+            };
+
+            loop {
+                let before = (remaining_qty, level.len());
+
+                // resting orders that cannot take what they are offered sit this round out
+                let mut sizes: Vec<u64> = level
+                    .iter()
+                    .map(|o| if o.resting_min_fill() <= remaining_qty { o.qty } else { 0 })
+                    .collect();
+                let allocations = loop {
+                    let allocations = self.policy.allocate(&sizes, remaining_qty);
+                    let short: Vec<usize> = (0..allocations.len())
+                        .filter(|i| allocations[*i] > 0 && allocations[*i] < level[*i].resting_min_fill())
+                        .collect();
+                    if short.is_empty() {
+                        break allocations;
+                    }
+                    for i in short {
+                        sizes[i] = 0;
+                    }
+                };
 
-                            let trade_qty = remaining_qty.min(bid_order.qty);
+                let mut kept = VecDeque::with_capacity(level.len());
+                let mut refilled = Vec::new();
+                let mut reallocate = false;
 
-                            let trade = Trade{
-                                id: uuid::Uuid::new_v4(),
-                                buyer: bid_order.user_id.clone(),
-                                seller: incoming_order.user_id.clone(),
-                                qty: trade_qty,
-                                price: bid_price
-                            };
+                for (mut resting, allocation) in level.drain(..).zip(allocations.into_iter().chain(std::iter::repeat(0))) {
+                    if reallocate || allocation == 0 || remaining_qty == 0 {
+                        kept.push_back(resting);
+                        continue;
+                    }
 
-                            fills.push(trade);
+                    if resting.user_id == incoming_order.user_id {
+                        kept.extend(prevent_self_trade(
+                            self_trade_prevention,
+                            &incoming_order,
+                            &mut remaining_qty,
+                            resting,
+                            &mut canceled_orders,
+                            &mut decremented,
+                        ));
+                        reallocate = true;
+                        continue;
+                    }
 
-                            remaining_qty -= trade_qty;
-                            bid_order.qty -= trade_qty;
+                    let trade_qty = allocation.min(remaining_qty);
+                    fills.push(Trade::new(&incoming_order, &resting, trade_qty, level_price));
 
-                            if bid_order.qty > 0 {
-                                bid_orders.push_front(bid_order);
-                                break; // Move to next price level
-                            }
-                        }
+                    remaining_qty -= trade_qty;
+                    resting.qty -= trade_qty;
 
-                        if bid_orders.is_empty() {
-                            self.bids.remove(&bid_price);
-                        }
+                    if resting.qty > 0 {
+                        kept.push_back(resting);
+                    } else if resting.reserve_qty > 0 {
+                        // the refilled iceberg slice queues at the back and loses time priority
+                        resting.show_display_slice();
+                        refilled.push(resting);
+                    } else {
+                        self.order_index.remove(&resting.id);
                     }
+                }
 
+                // refilled slices go to the back of the displayed orders, ahead of hidden ones
+                let first_hidden = kept.iter().position(|o| o.hidden).unwrap_or(kept.len());
+                for (offset, order) in refilled.into_iter().enumerate() {
+                    kept.insert(first_hidden + offset, order);
                 }
+                *level = kept;
 
-                
+                if remaining_qty == 0 || level.is_empty() || before == (remaining_qty, level.len()) {
+                    break;
+                }
+            }
 
+            if level.is_empty() {
+                book_side.remove(&level_price);
             }
         }
 
-        let remaining_order = (remaining_qty > 0).then(|| Order {
-            id: incoming_order.id,
-            user_id: incoming_order.user_id,
+        for order_id in &canceled_orders {
+            self.order_index.remove(order_id);
+        }
+
+        let remaining_order = (remaining_qty > 0).then_some(Order {
             qty: remaining_qty,
-            price: incoming_order.price,
-            side: incoming_order.side,
+            ..incoming_order
         });
 
-        (fills, remaining_order)
+        MatchOutcome {
+            trades: fills,
+            remaining_order,
+            canceled_orders,
+            decremented,
+        }
+    }
+
+    /// Where the book would uncross if the auction ended now
+    pub fn indicative_uncross(&self, reference_price: Option<u64>) -> Option<Uncross> {
+        discover_uncross(&self.bids, &self.asks, reference_price)
     }
 
-    pub fn cancel_order(&mut self, side: Side, order_id: uuid::Uuid) -> bool {
+    /// Executes every crossing order at `price`. Bids are taken in price-time priority and
+    /// matched against the asks at or below `price`, so the matching policy and self-trade
+    /// prevention apply as in continuous trading. A bid left partly filled goes back to the
+    /// front of its level.
+    pub fn uncross(&mut self, price: u64) -> MatchOutcome {
+        let crossing_bids: Vec<Uuid> = self
+            .bids
+            .range(price..)
+            .rev()
+            .flat_map(|(_, orders)| orders.iter().map(|o| o.id))
+            .collect();
+
+        let mut trades = Vec::new();
+        let mut canceled_orders = Vec::new();
+        let mut decremented = Vec::new();
+        for order_id in crossing_bids {
+            if self.best_ask().is_none_or(|ask| ask > price) {
+                break;
+            }
+            let Some(bid) = self.cancel_order(order_id) else {
+                continue;
+            };
+
+            let limit_price = bid.price;
+            let outcome = self.match_order(Order {
+                qty: bid.total_qty(),
+                reserve_qty: 0,
+                price,
+                ..bid
+            });
+
+            trades.extend(outcome.trades.into_iter().map(|t| Trade { price, aggressor_side: None, ..t }));
+            canceled_orders.extend(outcome.canceled_orders);
+            decremented.extend(outcome.decremented);
+            if let Some(remaining) = outcome.remaining_order {
+                self.restore_order(Order { price: limit_price, ..remaining });
+            }
+        }
+
+        MatchOutcome {
+            trades,
+            remaining_order: None,
+            canceled_orders,
+            decremented,
+        }
+    }
+
+    pub fn get_order(&self, order_id: Uuid) -> Option<&Order> {
+        let (side, price) = self.order_index.get(&order_id)?;
+        let book_side = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+
+        book_side.get(price)?.iter().find(|o| o.id == order_id)
+    }
+
+    /// Shrinks a resting order to `new_qty` where it sits, keeping its place in the queue.
+    /// Returns `false` if the order is not resting or `new_qty` is not an actual reduction.
+    pub fn reduce_order(&mut self, order_id: Uuid, new_qty: u64) -> bool {
+        let Some((side, price)) = self.order_index.get(&order_id) else {
+            return false;
+        };
         let book_side = match side {
             Side::Bid => &mut self.bids,
             Side::Ask => &mut self.asks,
         };
+        save_level(&mut self.undo, side, *price, book_side.get(price));
 
-        let mut removed = false;
-        let prices: Vec<u64> = book_side.keys().cloned().collect();
-
-        for price in prices {
-            if let Some(orders) = book_side.get_mut(&price) {
-                orders.retain(|o| {
-                    if o.id == order_id {
-                        removed = true;
-                        false
-                    } else {
-                        true
-                    }
-                });
+        match book_side.get_mut(price).and_then(|orders| orders.iter_mut().find(|o| o.id == order_id)) {
+            Some(order) if new_qty > 0 && new_qty < order.total_qty() => {
+                order.reduce_qty_by(order.total_qty() - new_qty);
+                true
+            }
+            _ => false,
+        }
+    }
 
-                if orders.is_empty() {
-                    book_side.remove(&price);
-                }
+    /// Pulls every order whose expiry is at or before `now` off the book
+    pub fn remove_expired(&mut self, now: u64) -> Vec<Order> {
+        let due: Vec<u64> = self.expiries.range(..=now).map(|(expires_at, _)| *expires_at).collect();
 
-                if removed {
-                    break;
-                }
+        let mut expired = Vec::new();
+        for expires_at in due {
+            for order_id in self.expiries.remove(&expires_at).unwrap_or_default() {
+                expired.extend(self.cancel_order(order_id));
             }
         }
 
-        removed
+        expired
     }
 
+    /// Removes a resting order, going straight to its price level through the index
+    pub fn cancel_order(&mut self, order_id: Uuid) -> Option<Order> {
+        let (side, price) = self.order_index.remove(&order_id)?;
+        let book_side = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        save_level(&mut self.undo, &side, price, book_side.get(&price));
+
+        let orders = book_side.get_mut(&price)?;
+        let position = orders.iter().position(|o| o.id == order_id)?;
+        let order = orders.remove(position);
+
+        if orders.is_empty() {
+            book_side.remove(&price);
+        }
+
+        order
+    }
 }
 
+/// Keeps a copy of the level at `price` the first time it changes while an undo log is open
+fn save_level(undo: &mut Option<SavedLevels>, side: &Side, price: u64, level: Option<&VecDeque<Order>>) {
+    if let Some(saved) = undo {
+        saved.entry((side.clone(), price)).or_insert_with(|| level.cloned());
+    }
+}
+
+/// Handles a resting order that belongs to the incoming order's user instead of trading with it.
+/// Cancelling the incoming order zeroes `remaining_qty` so matching stops.
+/// Returns the resting order when it stays at the front of its level.
+fn prevent_self_trade(
+    mode: SelfTradePrevention,
+    incoming_order: &Order,
+    remaining_qty: &mut u64,
+    mut resting_order: Order,
+    canceled_orders: &mut Vec<Uuid>,
+    decremented: &mut Vec<(Uuid, u64)>,
+) -> Option<Order> {
+    match mode {
+        SelfTradePrevention::CancelNewest => {
+            *remaining_qty = 0;
+            canceled_orders.push(incoming_order.id);
+            Some(resting_order)
+        }
+        SelfTradePrevention::CancelOldest => {
+            canceled_orders.push(resting_order.id);
+            None
+        }
+        SelfTradePrevention::CancelBoth => {
+            *remaining_qty = 0;
+            canceled_orders.push(resting_order.id);
+            canceled_orders.push(incoming_order.id);
+            None
+        }
+        SelfTradePrevention::DecrementAndCancel => {
+            let decrement = (*remaining_qty).min(resting_order.total_qty());
+            *remaining_qty -= decrement;
+            resting_order.reduce_qty_by(decrement);
+            decremented.extend([(resting_order.id, decrement), (incoming_order.id, decrement)]);
+
+            if *remaining_qty == 0 {
+                canceled_orders.push(incoming_order.id);
+            }
+
+            if resting_order.total_qty() == 0 {
+                canceled_orders.push(resting_order.id);
+                None
+            } else {
+                resting_order.show_display_slice();
+                Some(resting_order)
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::MatchingPolicyKind;
+
+/// Trading rules a market is created with. Every field has a permissive default,
+/// so a market created with only an id accepts any positive price and quantity.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct MarketConfig {
+    pub matching_policy: MatchingPolicyKind,
+    /// Prices must be a multiple of this
+    pub tick_size: u64,
+    /// Quantities must be a multiple of this
+    pub lot_size: u64,
+    pub min_qty: u64,
+    pub max_qty: Option<u64>,
+    /// Smallest `price * qty` a limit order may have
+    pub min_notional: u64,
+    /// How far limit prices may stray from the static reference, the last auction or reopening
+    /// price, 100 = 1%. Market and stop orders are capped at the band instead.
+    pub static_band_bps: Option<u64>,
+    /// Same as `static_band_bps` but measured from the last trade
+    pub dynamic_band_bps: Option<u64>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+}
+
+/// Longest circuit breaker window, halt or reopening auction a market can be created with
+const MAX_DURATION_MS: u64 = 24 * 60 * 60 * 1_000;
+
+/// Halts a market whose last price moves too far too fast
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Largest move allowed between any trade in the window and the last one, 100 = 1%
+    pub move_bps: u64,
+    pub window_ms: u64,
+    /// How long the market stays halted
+    pub halt_ms: u64,
+    #[serde(default)]
+    pub reopen: Reopen,
+}
+
+impl CircuitBreaker {
+    fn longest_ms(&self) -> u64 {
+        let reopen_ms = match self.reopen {
+            Reopen::Continuous => 0,
+            Reopen::Auction { duration_ms } => duration_ms,
+        };
+        self.window_ms.max(self.halt_ms).max(reopen_ms)
+    }
+}
+
+/// How a halted market gets back to continuous trading
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum Reopen {
+    /// Straight back to continuous trading once the halt is over
+    #[default]
+    Continuous,
+    /// Through an opening auction that uncrosses `duration_ms` after the halt is over
+    Auction { duration_ms: u64 },
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            matching_policy: MatchingPolicyKind::default(),
+            tick_size: 1,
+            lot_size: 1,
+            min_qty: 1,
+            max_qty: None,
+            min_notional: 0,
+            static_band_bps: None,
+            dynamic_band_bps: None,
+            circuit_breaker: None,
+        }
+    }
+}
+
+/// Why an order broke its market's rules
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum OrderRejection {
+    ZeroQuantity,
+    ZeroPrice,
+    OffTick { tick_size: u64 },
+    OffLot { lot_size: u64 },
+    BelowMinQty { min_qty: u64 },
+    AboveMaxQty { max_qty: u64 },
+    BelowMinNotional { min_notional: u64 },
+    /// `price * qty` does not fit in a balance
+    NotionalTooLarge,
+    OutsideStaticBand { low: u64, high: u64 },
+    OutsideDynamicBand { low: u64, high: u64 },
+}
+
+impl fmt::Display for OrderRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderRejection::ZeroQuantity => write!(f, "quantity must be greater than zero"),
+            OrderRejection::ZeroPrice => write!(f, "price must be greater than zero"),
+            OrderRejection::OffTick { tick_size } => write!(f, "price is not a multiple of the tick size {}", tick_size),
+            OrderRejection::OffLot { lot_size } => write!(f, "quantity is not a multiple of the lot size {}", lot_size),
+            OrderRejection::BelowMinQty { min_qty } => write!(f, "quantity is below the minimum of {}", min_qty),
+            OrderRejection::AboveMaxQty { max_qty } => write!(f, "quantity is above the maximum of {}", max_qty),
+            OrderRejection::BelowMinNotional { min_notional } => write!(f, "notional is below the minimum of {}", min_notional),
+            OrderRejection::NotionalTooLarge => write!(f, "notional is too large"),
+            OrderRejection::OutsideStaticBand { low, high } => write!(f, "price is outside the static band {}-{}", low, high),
+            OrderRejection::OutsideDynamicBand { low, high } => write!(f, "price is outside the dynamic band {}-{}", low, high),
+        }
+    }
+}
+
+impl MarketConfig {
+    /// Why the config itself cannot be used, if it cannot
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.tick_size == 0 {
+            return Err("Tick size must be greater than zero");
+        }
+        if self.lot_size == 0 {
+            return Err("Lot size must be greater than zero");
+        }
+        if self.max_qty.is_some_and(|max_qty| max_qty < self.min_qty) {
+            return Err("Maximum quantity is below the minimum quantity");
+        }
+        if self.circuit_breaker.is_some_and(|breaker| breaker.move_bps == 0 || breaker.window_ms == 0) {
+            return Err("Circuit breaker move and window must be greater than zero");
+        }
+        if self.circuit_breaker.is_some_and(|breaker| breaker.longest_ms() > MAX_DURATION_MS) {
+            return Err("Circuit breaker window, halt and reopening auction cannot be longer than a day");
+        }
+        Ok(())
+    }
+
+    /// Checks a quantity against the lot size and the quantity limits
+    pub fn check_qty(&self, qty: u64) -> Result<(), OrderRejection> {
+        if qty == 0 {
+            return Err(OrderRejection::ZeroQuantity);
+        }
+        if !qty.is_multiple_of(self.lot_size) {
+            return Err(OrderRejection::OffLot { lot_size: self.lot_size });
+        }
+        if qty < self.min_qty {
+            return Err(OrderRejection::BelowMinQty { min_qty: self.min_qty });
+        }
+        if let Some(max_qty) = self.max_qty.filter(|max_qty| qty > *max_qty) {
+            return Err(OrderRejection::AboveMaxQty { max_qty });
+        }
+        Ok(())
+    }
+
+    /// Checks a price against the tick size
+    pub fn check_price(&self, price: u64) -> Result<(), OrderRejection> {
+        if price == 0 {
+            return Err(OrderRejection::ZeroPrice);
+        }
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(OrderRejection::OffTick { tick_size: self.tick_size });
+        }
+        Ok(())
+    }
+
+    /// Every check a priced order has to pass
+    pub fn check_order(&self, price: u64, qty: u64) -> Result<(), OrderRejection> {
+        self.check_qty(qty)?;
+        self.check_price(price)?;
+        if price.saturating_mul(qty) < self.min_notional {
+            return Err(OrderRejection::BelowMinNotional { min_notional: self.min_notional });
+        }
+        Ok(())
+    }
+}
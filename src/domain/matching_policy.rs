@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use serde::Deserialize;
+
+/// Decides how an incoming order's quantity is shared out among the resting orders of one
+/// price level. `match_order` walks levels in price priority and asks the policy at each one.
+pub trait MatchingPolicy: Send + Sync {
+    /// Quantity each resting order gets out of `qty`, given the sizes it can take in queue order.
+    /// An allocation never exceeds its size and together they never exceed `qty`.
+    fn allocate(&self, sizes: &[u64], qty: u64) -> Vec<u64>;
+}
+
+/// Price-time priority, the front of the queue fills first
+pub struct Fifo;
+
+impl MatchingPolicy for Fifo {
+    fn allocate(&self, sizes: &[u64], qty: u64) -> Vec<u64> {
+        fill_in_queue_order(sizes, vec![0; sizes.len()], qty)
+    }
+}
+
+/// Shares the quantity in proportion to resting size. Shares below `min_allocation` are
+/// dropped and whatever is left after rounding goes out in time priority.
+pub struct ProRata {
+    pub min_allocation: u64,
+}
+
+impl MatchingPolicy for ProRata {
+    fn allocate(&self, sizes: &[u64], qty: u64) -> Vec<u64> {
+        pro_rata(sizes, qty, self.min_allocation)
+    }
+}
+
+/// The order at the front of the queue fills first, the rest of the level shares
+/// what is left pro-rata
+pub struct FifoTopProRata {
+    pub min_allocation: u64,
+}
+
+impl MatchingPolicy for FifoTopProRata {
+    fn allocate(&self, sizes: &[u64], qty: u64) -> Vec<u64> {
+        let Some((top, rest)) = sizes.split_first() else {
+            return vec![];
+        };
+
+        let top_allocation = (*top).min(qty);
+        let mut allocations = vec![top_allocation];
+        allocations.extend(pro_rata(rest, qty - top_allocation, self.min_allocation));
+        allocations
+    }
+}
+
+/// Matching policy picked for a market when it is created
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum MatchingPolicyKind {
+    #[default]
+    Fifo,
+    ProRata { min_allocation: u64 },
+    FifoTopProRata { min_allocation: u64 },
+}
+
+impl MatchingPolicyKind {
+    pub fn build(self) -> Arc<dyn MatchingPolicy> {
+        match self {
+            MatchingPolicyKind::Fifo => Arc::new(Fifo),
+            MatchingPolicyKind::ProRata { min_allocation } => Arc::new(ProRata { min_allocation }),
+            MatchingPolicyKind::FifoTopProRata { min_allocation } => Arc::new(FifoTopProRata { min_allocation }),
+        }
+    }
+}
+
+fn pro_rata(sizes: &[u64], qty: u64, min_allocation: u64) -> Vec<u64> {
+    let total: u64 = sizes.iter().sum();
+    if total <= qty {
+        return sizes.to_vec();
+    }
+
+    let allocations: Vec<u64> = sizes
+        .iter()
+        .map(|q| {
+            let share = (qty as u128 * *q as u128 / total as u128) as u64;
+            if share < min_allocation { 0 } else { share }
+        })
+        .collect();
+
+    let allocated: u64 = allocations.iter().sum();
+    fill_in_queue_order(sizes, allocations, qty - allocated)
+}
+
+/// Tops up `allocations` from the front of the queue until `left` runs out
+fn fill_in_queue_order(sizes: &[u64], mut allocations: Vec<u64>, mut left: u64) -> Vec<u64> {
+    for (allocation, q) in allocations.iter_mut().zip(sizes) {
+        if left == 0 {
+            break;
+        }
+        let extra = (q - *allocation).min(left);
+        *allocation += extra;
+        left -= extra;
+    }
+
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use crate::domain::{MarketBook, MarketConfig, Order, Side};
+
+    fn ask(user_id: &str, qty: u64) -> Order {
+        Order::new(user_id.to_string(), qty, 100, Side::Ask)
+    }
+
+    fn book(matching_policy: MatchingPolicyKind, asks: &[&Order]) -> MarketBook {
+        let mut book = MarketBook::with_config(&MarketConfig { matching_policy, ..MarketConfig::default() });
+        for order in asks {
+            book.insert_order((*order).clone());
+        }
+        book
+    }
+
+    /// Quantity each resting order got, in the order the fills happened
+    fn fills(book: &mut MarketBook, qty: u64) -> Vec<(Uuid, u64)> {
+        let outcome = book.match_order(Order::new("taker".to_string(), qty, 100, Side::Bid));
+        outcome.trades.iter().map(|t| (t.maker_order_id, t.qty)).collect()
+    }
+
+    #[test]
+    fn fifo_fills_from_the_front_of_the_queue() {
+        assert_eq!(Fifo.allocate(&[3, 4, 5], 5), vec![3, 2, 0]);
+    }
+
+    #[test]
+    fn pro_rata_rounds_down_and_tops_up_from_the_front() {
+        let policy = ProRata { min_allocation: 0 };
+        // 2.5, 1.5 and 1 round down to 2, 1 and 1, the one left over goes to the front
+        assert_eq!(policy.allocate(&[5, 3, 2], 5), vec![3, 1, 1]);
+    }
+
+    #[test]
+    fn pro_rata_drops_shares_below_the_minimum_allocation() {
+        let policy = ProRata { min_allocation: 2 };
+        // the two 1-lot shares are dropped and their quantity goes out in time priority
+        assert_eq!(policy.allocate(&[8, 2, 2], 6), vec![6, 0, 0]);
+    }
+
+    #[test]
+    fn pro_rata_fills_everyone_when_the_level_is_smaller_than_the_order() {
+        assert_eq!(ProRata { min_allocation: 5 }.allocate(&[3, 2], 10), vec![3, 2]);
+    }
+
+    #[test]
+    fn fifo_top_pro_rata_fills_the_top_order_then_shares_the_rest() {
+        let policy = FifoTopProRata { min_allocation: 0 };
+        // 4 to the top, then 3 shared 2.25 and 0.75, rounded down to 2 and 0 and topped up
+        assert_eq!(policy.allocate(&[4, 6, 2], 7), vec![4, 3, 0]);
+        assert_eq!(policy.allocate(&[4, 6], 3), vec![3, 0]);
+        assert!(policy.allocate(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn all_or_none_orders_the_share_cannot_fill_sit_the_level_out() {
+        let all_or_none = Order { all_or_none: true, ..ask("a", 10) };
+        let (b, c) = (ask("b", 3), ask("c", 4));
+        let mut book = book(MatchingPolicyKind::Fifo, &[&all_or_none, &b, &c]);
+
+        assert_eq!(fills(&mut book, 5), vec![(b.id, 3), (c.id, 2)]);
+        let level: Vec<(Uuid, u64)> = book.asks[&100].iter().map(|o| (o.id, o.qty)).collect();
+        assert_eq!(level, vec![(all_or_none.id, 10), (c.id, 2)]);
+    }
+
+    #[test]
+    fn share_below_a_resting_minimum_is_allocated_again_without_it() {
+        let minimum = Order { min_qty: Some(4), ..ask("a", 6) };
+        let other = ask("b", 6);
+        let mut book = book(MatchingPolicyKind::ProRata { min_allocation: 0 }, &[&minimum, &other]);
+
+        // an even split gives 3 each, below the first order's minimum of 4
+        assert_eq!(fills(&mut book, 6), vec![(other.id, 6)]);
+        assert_eq!(book.asks[&100].iter().map(|o| o.qty).collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn refilled_iceberg_slice_is_allocated_again_from_the_back_of_the_level() {
+        let iceberg = Order { display_qty: Some(2), ..ask("a", 5) };
+        let other = ask("b", 3);
+        let mut book = book(MatchingPolicyKind::Fifo, &[&iceberg, &other]);
+
+        assert_eq!(fills(&mut book, 6), vec![(iceberg.id, 2), (other.id, 3), (iceberg.id, 1)]);
+        let level: Vec<(u64, u64)> = book.asks[&100].iter().map(|o| (o.qty, o.reserve_qty)).collect();
+        assert_eq!(level, vec![(1, 1)]);
+    }
+}
@@ -1,10 +1,28 @@
 pub mod user;
 pub mod order;
+pub mod order_tracker;
 pub mod market_book;
+pub mod matching_policy;
+pub mod market_config;
 pub mod trade;
+pub mod trigger_book;
+pub mod market;
+pub mod auction;
+pub mod clock;
+pub mod ledger;
+pub mod withdrawal;
 
-pub use user::User;
-pub use order::{Order, OrderSummary, Side};
-pub use market_book::MarketBook;
+pub use user::{AssetBalance, User};
+pub use order::{Order, OrderSummary, Peg, PegReference, PostOnly, PostOnlyOutcome, SelfTradePrevention, Side, TimeInForce};
+pub use order_tracker::{OrderState, OrderStatus, OrderTracker};
+pub use market_book::{MarketBook, MatchOutcome};
+pub use matching_policy::{Fifo, FifoTopProRata, MatchingPolicy, MatchingPolicyKind, ProRata};
+pub use market_config::{CircuitBreaker, MarketConfig, OrderRejection, Reopen};
 pub use trade::Trade;
+pub use trigger_book::{StopOrder, TrailBy, TrailingStop, TriggerBook};
+pub use market::{Checkpoint, Market};
+pub use auction::{discover_uncross, AuctionKind, TradingPhase, Uncross};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use ledger::{Account, AssetTotals, ConservationReport, Entry, EntrySide, Ledger, Transaction, TransactionKind};
+pub use withdrawal::{PayoutFuture, PayoutProvider, StubPayoutProvider, Withdrawal, WithdrawalPolicy, WithdrawalStatus};
 
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Side {
     /// A buy order 
     Bid,
@@ -9,6 +9,67 @@ pub enum Side {
     Ask,
 }
 
+/// How long a limit order stays working once it reaches the book
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    /// Good-til-cancelled, any unfilled remainder rests on the book
+    #[default]
+    Gtc,
+    /// Immediate-or-cancel, fill what crosses now and drop the remainder
+    Ioc,
+    /// Fill-or-kill, fill the whole quantity now or nothing at all
+    Fok,
+}
+
+/// What to do with a post-only order that would take liquidity
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PostOnly {
+    /// Reject the order outright
+    Reject,
+    /// Move the price one tick away from the opposite best so it rests passively
+    Reprice,
+}
+
+/// What actually happened to a post-only order
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PostOnlyOutcome {
+    Accepted,
+    Repriced { price: u64 },
+    Rejected,
+}
+
+/// What happens when an order would trade against another order of the same user
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel the incoming order, the resting one keeps its place
+    #[default]
+    CancelNewest,
+    /// Cancel the resting order and keep matching the incoming one
+    CancelOldest,
+    /// Cancel both orders
+    CancelBoth,
+    /// Reduce both orders by the smaller quantity, cancelling whichever reaches zero
+    DecrementAndCancel,
+}
+
+/// Price a pegged order follows
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PegReference {
+    BestBid,
+    BestAsk,
+    /// Halfway between best bid and best ask, rounded away from the opposite side
+    Mid,
+}
+
+/// Keeps an order priced at `reference + offset` as the top of the book moves
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Peg {
+    pub reference: PegReference,
+    #[serde(default)]
+    pub offset: i64,
+}
+
 ///Order with its types ...
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct  Order {
@@ -17,6 +78,32 @@ pub struct  Order {
     pub qty: u64,
     pub price: u64,
     pub side: Side,
+    /// Peak size of an iceberg order, `qty` never shows more than this while resting
+    #[serde(default)]
+    pub display_qty: Option<u64>,
+    /// Hidden iceberg quantity used to refill `qty` once the visible slice trades away
+    #[serde(default)]
+    pub reserve_qty: u64,
+    /// Self-trade mode for this order, falls back to the account setting and then the default
+    #[serde(default)]
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    /// Good-til-time, unix milliseconds after which the order is pulled from the book
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Pegged orders have `price` rewritten whenever their reference moves
+    #[serde(default)]
+    pub peg: Option<Peg>,
+    /// Only ever trades its whole remaining quantity in one go
+    #[serde(default)]
+    pub all_or_none: bool,
+    /// Smallest fill the order accepts, summed over the fills on entry
+    /// and for each counterparty once it rests
+    #[serde(default)]
+    pub min_qty: Option<u64>,
+    /// Rests and trades like any other order but never shows in the book,
+    /// queuing behind the displayed orders at its price
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl Order {
@@ -27,6 +114,62 @@ impl Order {
             qty,
             price,
             side,
+            display_qty: None,
+            reserve_qty: 0,
+            self_trade_prevention: None,
+            expires_at: None,
+            peg: None,
+            all_or_none: false,
+            min_qty: None,
+            hidden: false,
+        }
+    }
+
+    /// Quantity an incoming order has to fill on entry before any of it trades, `None` without a constraint
+    pub fn entry_min_fill(&self) -> Option<u64> {
+        if self.all_or_none {
+            Some(self.qty)
+        } else {
+            self.min_qty.map(|min_qty| min_qty.min(self.qty))
+        }
+    }
+
+    /// Smallest fill this order takes from a single counterparty while resting
+    pub fn resting_min_fill(&self) -> u64 {
+        if self.all_or_none {
+            self.total_qty()
+        } else {
+            self.min_qty.unwrap_or(1).min(self.total_qty())
+        }
+    }
+
+    /// Quantity still working, the visible slice plus any hidden reserve
+    pub fn total_qty(&self) -> u64 {
+        self.qty + self.reserve_qty
+    }
+
+    /// Shows at most the display size and moves the rest into the reserve. No-op for plain orders.
+    pub fn show_display_slice(&mut self) {
+        if let Some(display_qty) = self.display_qty {
+            let total = self.total_qty();
+            self.qty = total.min(display_qty);
+            self.reserve_qty = total - self.qty;
+        }
+    }
+
+    /// Takes `cut` off the working quantity, draining the hidden reserve before the visible slice
+    pub fn reduce_qty_by(&mut self, cut: u64) {
+        let from_reserve = cut.min(self.reserve_qty);
+        self.reserve_qty -= from_reserve;
+        self.qty -= cut - from_reserve;
+    }
+
+    /// The order as other participants may see it, with any iceberg reserve left out
+    pub fn public_view(&self) -> Self {
+        Self {
+            display_qty: None,
+            reserve_qty: 0,
+            ..self.clone()
         }
     }
 }
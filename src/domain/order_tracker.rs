@@ -0,0 +1,258 @@
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::domain::{Side, Trade};
+
+/// Where an order is in its life. `New` and `PartiallyFilled` are still working,
+/// the rest are final.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+}
+
+/// What is known about one order, kept after it leaves the book
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderState {
+    pub order_id: Uuid,
+    pub market_id: u64,
+    pub user_id: String,
+    pub side: Side,
+    /// Limit price, `None` for market and stop-market orders
+    pub price: Option<u64>,
+    /// Total quantity ordered, filled quantity included
+    pub qty: u64,
+    pub filled_qty: u64,
+    /// Quantity-weighted price of every fill, `None` before the first one
+    pub avg_fill_price: Option<f64>,
+    pub status: OrderStatus,
+    /// Why the order was rejected, or cancelled by the exchange rather than its user
+    pub reason: Option<String>,
+    #[serde(skip)]
+    filled_notional: u128,
+}
+
+impl OrderState {
+    pub fn new(order_id: Uuid, user_id: &str, side: Side, qty: u64, price: Option<u64>) -> Self {
+        Self {
+            order_id,
+            market_id: 0,
+            user_id: user_id.to_string(),
+            side,
+            price,
+            qty,
+            filled_qty: 0,
+            avg_fill_price: None,
+            status: OrderStatus::New,
+            reason: None,
+            filled_notional: 0,
+        }
+    }
+
+    fn fill(&mut self, qty: u64, price: u64) {
+        self.filled_qty += qty;
+        self.filled_notional += qty as u128 * price as u128;
+        self.avg_fill_price = Some(self.filled_notional as f64 / self.filled_qty as f64);
+
+        if self.status.is_open() {
+            self.status = if self.filled_qty >= self.qty { OrderStatus::Filled } else { OrderStatus::PartiallyFilled };
+        }
+    }
+}
+
+/// Most orders with a final status a market remembers. Past it the oldest are forgotten
+/// and no longer found by id, working orders are always kept.
+const MAX_FINISHED_ORDERS: usize = 100_000;
+
+/// The tracker as it was before the changes made since `begin_undo`
+struct TrackerUndo {
+    /// Earlier state of every order changed, `None` for one that was not tracked
+    states: HashMap<Uuid, Option<OrderState>>,
+    closed_len: usize,
+    finished_len: usize,
+}
+
+/// Lifecycle of every order a market has seen, accepted or not, up to `MAX_FINISHED_ORDERS`
+/// that have ended
+pub struct OrderTracker {
+    market_id: u64,
+    orders: HashMap<Uuid, OrderState>,
+    /// Orders that reached a final status since the last `take_closed`
+    closed: Vec<Uuid>,
+    /// Every order with a final status, oldest first
+    finished: VecDeque<Uuid>,
+    /// Recorded only between `begin_undo` and `commit`
+    undo: Option<TrackerUndo>,
+}
+
+impl OrderTracker {
+    pub fn new(market_id: u64) -> Self {
+        Self {
+            market_id,
+            orders: HashMap::new(),
+            closed: Vec::new(),
+            finished: VecDeque::new(),
+            undo: None,
+        }
+    }
+
+    /// Opens an undo log of every order state before its first change and of how far the closed and
+    /// finished lists reached, see `Market::checkpoint`
+    pub fn begin_undo(&mut self) {
+        self.undo = Some(TrackerUndo {
+            states: HashMap::new(),
+            closed_len: self.closed.len(),
+            finished_len: self.finished.len(),
+        });
+    }
+
+    pub fn commit(&mut self) {
+        self.undo = None;
+    }
+
+    pub fn rollback(&mut self) {
+        let Some(undo) = self.undo.take() else {
+            return;
+        };
+
+        for (order_id, state) in undo.states {
+            match state {
+                Some(state) => self.orders.insert(order_id, state),
+                None => self.orders.remove(&order_id),
+            };
+        }
+        self.closed.truncate(undo.closed_len);
+        self.finished.truncate(undo.finished_len);
+    }
+
+    /// Keeps the state of `order_id` before its first change while an undo log is open
+    fn save(&mut self, order_id: Uuid) {
+        if let Some(undo) = &mut self.undo {
+            undo.states.entry(order_id).or_insert_with(|| self.orders.get(&order_id).cloned());
+        }
+    }
+
+    pub fn get(&self, order_id: Uuid) -> Option<&OrderState> {
+        self.orders.get(&order_id)
+    }
+
+    /// Working orders of `user_id`, only those on `side` when one is given
+    pub fn open_orders(&self, user_id: &str, side: Option<&Side>) -> Vec<&OrderState> {
+        self.orders
+            .values()
+            .filter(|state| state.status.is_open() && state.user_id == user_id)
+            .filter(|state| side.is_none_or(|side| state.side == *side))
+            .collect()
+    }
+
+    /// Starts tracking an accepted order as `New`
+    pub fn open(&mut self, state: OrderState) {
+        let state = OrderState { market_id: self.market_id, ..state };
+        self.save(state.order_id);
+        self.orders.insert(state.order_id, state);
+    }
+
+    /// Records an order turned away before it was accepted. An order already
+    /// tracked keeps its state, it was accepted and ended some other way.
+    pub fn reject(&mut self, state: OrderState, reason: &str) {
+        if self.orders.contains_key(&state.order_id) {
+            return;
+        }
+        self.save(state.order_id);
+        self.finished.push_back(state.order_id);
+        self.orders.insert(state.order_id, OrderState {
+            market_id: self.market_id,
+            status: OrderStatus::Rejected,
+            reason: Some(reason.to_string()),
+            ..state
+        });
+    }
+
+    /// Adds each trade to its maker and taker
+    pub fn record_fills(&mut self, trades: &[Trade]) {
+        for trade in trades {
+            for order_id in [trade.maker_order_id, trade.taker_order_id] {
+                self.save(order_id);
+                if let Some(state) = self.orders.get_mut(&order_id) {
+                    let was_open = state.status.is_open();
+                    state.fill(trade.qty, trade.price);
+                    if was_open && state.status == OrderStatus::Filled {
+                        self.closed.push(order_id);
+                        self.finished.push_back(order_id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resizes a working order to `qty` still open at `price`
+    pub fn amend(&mut self, order_id: Uuid, price: u64, qty: u64) {
+        self.save(order_id);
+        if let Some(state) = self.orders.get_mut(&order_id).filter(|state| state.status.is_open()) {
+            state.price = Some(price);
+            state.qty = state.filled_qty + qty;
+        }
+    }
+
+    /// Takes what self-trade prevention cut off a working order out of the quantity it ordered
+    pub fn decrement(&mut self, order_id: Uuid, qty: u64) {
+        self.save(order_id);
+        if let Some(state) = self.orders.get_mut(&order_id).filter(|state| state.status.is_open()) {
+            state.qty = state.qty.saturating_sub(qty).max(state.filled_qty);
+        }
+    }
+
+    /// Follows a pegged order to its new price
+    pub fn reprice(&mut self, order_id: Uuid, price: u64) {
+        self.save(order_id);
+        if let Some(state) = self.orders.get_mut(&order_id) {
+            state.price = Some(price);
+        }
+    }
+
+    pub fn cancel(&mut self, order_id: Uuid) {
+        self.close(order_id, OrderStatus::Cancelled, None);
+    }
+
+    /// Cancels a working order the exchange pulled, keeping why
+    pub fn cancel_with_reason(&mut self, order_id: Uuid, reason: &str) {
+        self.close(order_id, OrderStatus::Cancelled, Some(reason));
+    }
+
+    pub fn expire(&mut self, order_id: Uuid) {
+        self.close(order_id, OrderStatus::Expired, None);
+    }
+
+    /// Ends a working order, a final status is never overwritten
+    fn close(&mut self, order_id: Uuid, status: OrderStatus, reason: Option<&str>) {
+        self.save(order_id);
+        if let Some(state) = self.orders.get_mut(&order_id).filter(|state| state.status.is_open()) {
+            state.status = status;
+            state.reason = reason.map(str::to_string);
+            self.closed.push(order_id);
+            self.finished.push_back(order_id);
+        }
+    }
+
+    /// Orders that ended since the last call, whatever funds they still hold can go back.
+    /// Also forgets the oldest final orders past `MAX_FINISHED_ORDERS`.
+    pub fn take_closed(&mut self) -> Vec<Uuid> {
+        while self.finished.len() > MAX_FINISHED_ORDERS {
+            if let Some(order_id) = self.finished.pop_front() {
+                self.orders.remove(&order_id);
+            }
+        }
+        std::mem::take(&mut self.closed)
+    }
+}
@@ -1,25 +1,49 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::domain::{Order, User};
+use crate::domain::{Order, Side};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Trade {
     pub id: Uuid,
+    pub market_id: u64,
+    /// Position in the market's trade sequence, starting at 1 with no gaps
+    pub sequence: u64,
+    /// When the trade printed, unix nanoseconds
+    pub timestamp_nanos: u64,
     pub buyer: String,
     pub seller: String,
     pub qty: u64,
     pub price: u64,
+    /// Side of the order that took liquidity, `None` for trades printed by an auction uncross
+    pub aggressor_side: Option<Side>,
+    /// Resting order that provided liquidity, the sell order in an auction trade
+    pub maker_order_id: Uuid,
+    /// Order that took liquidity, the buy order in an auction trade
+    pub taker_order_id: Uuid,
 }
 
 impl Trade {
-    pub fn new(buyer: &User, order: &Order, seler: &User) -> Self{
-        Self { 
+    /// A fill between an incoming `taker` and a resting `maker`. Market, sequence and
+    /// timestamp are left for the market to stamp.
+    pub fn new(taker: &Order, maker: &Order, qty: u64, price: u64) -> Self {
+        let (buyer, seller) = match taker.side {
+            Side::Bid => (taker.user_id.clone(), maker.user_id.clone()),
+            Side::Ask => (maker.user_id.clone(), taker.user_id.clone()),
+        };
+
+        Self {
             id: Uuid::new_v4(),
-            buyer: buyer.email.clone(),
-            seller: seler.email.clone(),
-            qty: order.qty,
-            price: order.price
+            market_id: 0,
+            sequence: 0,
+            timestamp_nanos: 0,
+            buyer,
+            seller,
+            qty,
+            price,
+            aggressor_side: Some(taker.side.clone()),
+            maker_order_id: maker.id,
+            taker_order_id: taker.id,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::domain::{Order, Side};
+
+/// A conditional order waiting for the last trade price to reach `stop_price`.
+/// Without a `limit_price` it fires as a market order, otherwise as a limit order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StopOrder {
+    pub id: Uuid,
+    pub user_id: String,
+    pub side: Side,
+    pub qty: u64,
+    pub stop_price: u64,
+    pub limit_price: Option<u64>,
+}
+
+impl StopOrder {
+    pub fn new(user_id: String, side: Side, qty: u64, stop_price: u64, limit_price: Option<u64>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            side,
+            qty,
+            stop_price,
+            limit_price,
+        }
+    }
+
+    /// Whether a trade at `last_price` fires this stop.
+    /// Buy stops fire when the market trades at or above the stop, sell stops at or below it.
+    pub fn is_triggered_by(&self, last_price: u64) -> bool {
+        match self.side {
+            Side::Bid => last_price >= self.stop_price,
+            Side::Ask => last_price <= self.stop_price,
+        }
+    }
+
+    /// The order sent to the book once the stop fires. Stop-market orders sweep every level.
+    pub fn into_order(self) -> Order {
+        let price = self.limit_price.unwrap_or(match self.side {
+            Side::Bid => u64::MAX,
+            Side::Ask => 0,
+        });
+
+        Order {
+            id: self.id,
+            ..Order::new(self.user_id, self.qty, price, self.side)
+        }
+    }
+}
+
+/// How far a trailing stop sits behind its reference price
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TrailBy {
+    /// A fixed number of price ticks
+    Amount(u64),
+    /// A share of the reference price, 100 = 1%, at most 10_000
+    BasisPoints(u64),
+}
+
+impl TrailBy {
+    pub fn distance(&self, reference_price: u64) -> u64 {
+        match self {
+            TrailBy::Amount(amount) => *amount,
+            TrailBy::BasisPoints(bps) => u64::try_from(reference_price as u128 * *bps as u128 / 10_000).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// A stop whose stop price follows the market. A sell trails below the highest trade seen
+/// since it was placed, a buy trails above the lowest, and either fires once price turns back
+/// by the trail distance. Without a `limit_offset` it fires as a market order, otherwise as a
+/// limit order that far beyond the stop price.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrailingStop {
+    pub id: Uuid,
+    pub user_id: String,
+    pub side: Side,
+    pub qty: u64,
+    pub trail: TrailBy,
+    pub limit_offset: Option<u64>,
+    pub reference_price: u64,
+}
+
+impl TrailingStop {
+    pub fn new(user_id: String, side: Side, qty: u64, trail: TrailBy, limit_offset: Option<u64>, reference_price: u64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            side,
+            qty,
+            trail,
+            limit_offset,
+            reference_price,
+        }
+    }
+
+    pub fn stop_price(&self) -> u64 {
+        let distance = self.trail.distance(self.reference_price);
+        match self.side {
+            Side::Bid => self.reference_price.saturating_add(distance),
+            Side::Ask => self.reference_price.saturating_sub(distance),
+        }
+    }
+
+    /// Moves the reference with a favourable trade and reports whether the trade fired the stop
+    pub fn on_trade(&mut self, price: u64) -> bool {
+        match self.side {
+            Side::Bid if price < self.reference_price => self.reference_price = price,
+            Side::Ask if price > self.reference_price => self.reference_price = price,
+            _ => {}
+        }
+
+        match self.side {
+            Side::Bid => price >= self.stop_price(),
+            Side::Ask => price <= self.stop_price(),
+        }
+    }
+
+    /// Freezes the trail at its current stop price into the stop order that gets executed
+    pub fn into_stop_order(self) -> StopOrder {
+        let stop_price = self.stop_price();
+        let limit_price = self.limit_offset.map(|offset| match self.side {
+            Side::Bid => stop_price.saturating_add(offset),
+            Side::Ask => stop_price.saturating_sub(offset),
+        });
+
+        StopOrder {
+            id: self.id,
+            ..StopOrder::new(self.user_id, self.side, self.qty, stop_price, limit_price)
+        }
+    }
+}
+
+/// What firing stops took out of the trigger book since `begin_undo`
+#[derive(Default)]
+struct FiredStops {
+    levels: Vec<(Side, u64, VecDeque<StopOrder>)>,
+    /// Trailing stops as they were before the first trade moved them
+    trailing_stops: Option<Vec<TrailingStop>>,
+}
+
+/// Resting stop orders of a market, keyed by stop price, plus trailing stops
+/// whose stop price moves with every trade
+pub struct TriggerBook {
+    pub buy_stops: BTreeMap<u64, VecDeque<StopOrder>>,
+    pub sell_stops: BTreeMap<u64, VecDeque<StopOrder>>,
+    pub trailing_stops: Vec<TrailingStop>,
+    /// Recorded only between `begin_undo` and `commit`
+    undo: Option<FiredStops>,
+}
+
+impl Default for TriggerBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TriggerBook {
+    pub fn new() -> Self {
+        Self {
+            buy_stops: BTreeMap::new(),
+            sell_stops: BTreeMap::new(),
+            trailing_stops: Vec::new(),
+            undo: None,
+        }
+    }
+
+    /// Opens an undo log of the stops `take_triggered` fires and the trailing stops it moves,
+    /// see `Market::checkpoint`
+    pub fn begin_undo(&mut self) {
+        self.undo = Some(FiredStops::default());
+    }
+
+    pub fn commit(&mut self) {
+        self.undo = None;
+    }
+
+    pub fn rollback(&mut self) {
+        let Some(fired) = self.undo.take() else {
+            return;
+        };
+
+        for (side, price, stops) in fired.levels {
+            let book_side = match side {
+                Side::Bid => &mut self.buy_stops,
+                Side::Ask => &mut self.sell_stops,
+            };
+            book_side.insert(price, stops);
+        }
+        if let Some(trailing_stops) = fired.trailing_stops {
+            self.trailing_stops = trailing_stops;
+        }
+    }
+
+    pub fn insert(&mut self, stop: StopOrder) {
+        let target_side = match stop.side {
+            Side::Bid => &mut self.buy_stops,
+            Side::Ask => &mut self.sell_stops,
+        };
+
+        target_side.entry(stop.stop_price).or_default().push_back(stop);
+    }
+
+    pub fn insert_trailing(&mut self, stop: TrailingStop) {
+        self.trailing_stops.push(stop);
+    }
+
+    /// Removes and returns every stop fired by a trade at `last_price`, in the order the market reached them.
+    /// Trailing stops update their reference price first and come after the fixed stops.
+    pub fn take_triggered(&mut self, last_price: u64) -> Vec<StopOrder> {
+        let mut fired = Vec::new();
+
+        // buy stops at or below the last price, lowest stop first
+        let buy_prices: Vec<u64> = self.buy_stops.range(..=last_price).map(|(price, _)| *price).collect();
+        for price in buy_prices {
+            if let Some(stops) = self.buy_stops.remove(&price) {
+                if let Some(undo) = &mut self.undo {
+                    undo.levels.push((Side::Bid, price, stops.clone()));
+                }
+                fired.extend(stops);
+            }
+        }
+
+        // sell stops at or above the last price, highest stop first
+        let sell_prices: Vec<u64> = self.sell_stops.range(last_price..).rev().map(|(price, _)| *price).collect();
+        for price in sell_prices {
+            if let Some(stops) = self.sell_stops.remove(&price) {
+                if let Some(undo) = &mut self.undo {
+                    undo.levels.push((Side::Ask, price, stops.clone()));
+                }
+                fired.extend(stops);
+            }
+        }
+
+        if let Some(undo) = self.undo.as_mut().filter(|undo| undo.trailing_stops.is_none()) {
+            undo.trailing_stops = Some(self.trailing_stops.clone());
+        }
+        let mut still_trailing = Vec::new();
+        for mut stop in std::mem::take(&mut self.trailing_stops) {
+            if stop.on_trade(last_price) {
+                fired.push(stop.into_stop_order());
+            } else {
+                still_trailing.push(stop);
+            }
+        }
+        self.trailing_stops = still_trailing;
+
+        fired
+    }
+
+    /// User who placed the stop or trailing stop with this id
+    pub fn owner(&self, order_id: Uuid) -> Option<&str> {
+        self.buy_stops
+            .values()
+            .chain(self.sell_stops.values())
+            .flatten()
+            .find(|s| s.id == order_id)
+            .map(|s| s.user_id.as_str())
+            .or_else(|| self.trailing_stops.iter().find(|s| s.id == order_id).map(|s| s.user_id.as_str()))
+    }
+
+    pub fn cancel(&mut self, order_id: Uuid) -> bool {
+        if let Some(idx) = self.trailing_stops.iter().position(|s| s.id == order_id) {
+            self.trailing_stops.remove(idx);
+            return true;
+        }
+
+        for book_side in [&mut self.buy_stops, &mut self.sell_stops] {
+            let found = book_side.iter().find_map(|(price, stops)| {
+                stops.iter().position(|s| s.id == order_id).map(|idx| (*price, idx))
+            });
+
+            if let Some((price, idx)) = found {
+                if let Some(stops) = book_side.get_mut(&price) {
+                    stops.remove(idx);
+                    if stops.is_empty() {
+                        book_side.remove(&price);
+                    }
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+}
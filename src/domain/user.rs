@@ -1,12 +1,26 @@
-use crate::domain::Trade;
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+use crate::domain::{SelfTradePrevention, Trade};
+
+/// What a user has of one asset
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct AssetBalance {
+    /// Free to trade or lock
+    pub available: u64,
+    /// Locked by open orders until they fill or end
+    pub reserved: u64,
+}
 
 #[derive(Clone, Debug)]
 pub struct User {
     pub email: String,
     pub password: String,
-    pub balance: u64,
-    pub holdings: u64,
-    pub trades: Vec<Trade>
+    /// Balance of every asset the user has held, keyed by asset name
+    pub balances: BTreeMap<String, AssetBalance>,
+    pub trades: Vec<Trade>,
+    /// Account-wide self-trade mode, used when an order does not pick one
+    pub self_trade_prevention: Option<SelfTradePrevention>,
 }
 
 impl User {
@@ -14,10 +28,51 @@ impl User {
         Self {
             email,
             password,
-            balance: 0,
-            holdings: 0,
-            trades: vec![]
+            balances: BTreeMap::new(),
+            trades: vec![],
+            self_trade_prevention: None,
         }
     }
-}
 
+    pub fn balance(&self, asset: &str) -> AssetBalance {
+        self.balances.get(asset).copied().unwrap_or_default()
+    }
+
+    /// Adds `amount` to the available balance of `asset`
+    pub fn credit(&mut self, asset: &str, amount: u64) {
+        self.balance_mut(asset).available += amount;
+    }
+
+    /// Pays `amount` of `asset`, `from_reserved` of it out of the reserved balance and the rest
+    /// out of what is available. The caller makes sure the two cover it.
+    pub fn debit(&mut self, asset: &str, amount: u64, from_reserved: u64) {
+        let balance = self.balance_mut(asset);
+        let from_reserved = from_reserved.min(balance.reserved).min(amount);
+        balance.reserved -= from_reserved;
+        balance.available -= amount - from_reserved;
+    }
+
+    /// Moves `amount` of `asset` from available to reserved.
+    /// Nothing moves and `false` comes back when not enough is available.
+    pub fn reserve(&mut self, asset: &str, amount: u64) -> bool {
+        let balance = self.balance_mut(asset);
+        if amount > balance.available {
+            return false;
+        }
+        balance.available -= amount;
+        balance.reserved += amount;
+        true
+    }
+
+    /// Moves `amount` of `asset` back from reserved to available
+    pub fn release(&mut self, asset: &str, amount: u64) {
+        let balance = self.balance_mut(asset);
+        let amount = amount.min(balance.reserved);
+        balance.reserved -= amount;
+        balance.available += amount;
+    }
+
+    fn balance_mut(&mut self, asset: &str) -> &mut AssetBalance {
+        self.balances.entry(asset.to_string()).or_default()
+    }
+}
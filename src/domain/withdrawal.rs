@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+use std::future::{ready, Future};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Where a withdrawal is. Funds are held from `Pending` until it is `Rejected` or `Completed`.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum WithdrawalStatus {
+    /// Waiting for an admin
+    Pending,
+    /// Cleared for payout, stays here while the payout runs and after it fails
+    Approved,
+    /// Turned down by an admin, the held funds went back
+    Rejected,
+    /// Paid out, the funds left the exchange
+    Completed,
+}
+
+/// A request to take funds out of the exchange
+#[derive(Clone, Debug, Serialize)]
+pub struct Withdrawal {
+    pub id: Uuid,
+    pub user_email: String,
+    pub asset: String,
+    pub amount: u64,
+    /// Where the payout goes, passed on to the payout provider as is
+    pub destination: String,
+    pub status: WithdrawalStatus,
+    /// Whether the amount was over the threshold and needed an admin
+    pub requires_approval: bool,
+    /// Admin who approved or rejected the withdrawal
+    pub reviewed_by: Option<String>,
+    /// Why it was rejected, or why the last payout attempt failed
+    pub reason: Option<String>,
+    /// Reference the payout provider gave the payout
+    pub payout_reference: Option<String>,
+    /// Whether a payout was handed to the provider and has not come back yet
+    pub payout_in_flight: bool,
+}
+
+impl Withdrawal {
+    pub fn new(user_email: String, asset: String, amount: u64, destination: String, requires_approval: bool) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_email,
+            asset,
+            amount,
+            destination,
+            status: if requires_approval { WithdrawalStatus::Pending } else { WithdrawalStatus::Approved },
+            requires_approval,
+            reviewed_by: None,
+            reason: None,
+            payout_reference: None,
+            payout_in_flight: false,
+        }
+    }
+}
+
+/// Who may approve withdrawals and how much goes out without them. Set at startup.
+#[derive(Clone, Debug, Default)]
+pub struct WithdrawalPolicy {
+    /// Users allowed to approve or reject withdrawals
+    pub admins: HashSet<String>,
+    /// Largest amount per asset paid out without an admin. Withdrawals of an asset
+    /// not listed always need one.
+    pub approval_thresholds: HashMap<String, u64>,
+}
+
+impl WithdrawalPolicy {
+    pub fn is_admin(&self, email: &str) -> bool {
+        self.admins.contains(email)
+    }
+
+    pub fn needs_approval(&self, asset: &str, amount: u64) -> bool {
+        self.approval_thresholds.get(asset).is_none_or(|threshold| amount > *threshold)
+    }
+}
+
+/// A payout on its way, resolving to the provider's reference for it
+pub type PayoutFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+/// Sends withdrawn funds out of the exchange.
+/// Injected so the DB actor can run against a real rail or the in-process stub.
+pub trait PayoutProvider: Send + Sync {
+    /// Pays the withdrawal out. The DB actor runs the returned future on its own task, so a
+    /// slow rail never holds up balances or settlement.
+    fn pay_out(&self, withdrawal: Withdrawal) -> PayoutFuture;
+}
+
+/// Payout provider that pays nothing out, it only remembers what it was asked to pay.
+/// What the server runs with until a real provider is plugged in.
+#[derive(Default)]
+pub struct StubPayoutProvider {
+    failing: AtomicBool,
+    paid: Mutex<Vec<Uuid>>,
+}
+
+impl StubPayoutProvider {
+    /// Makes every payout fail until switched back
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    /// Withdrawals paid out so far, oldest first
+    pub fn paid(&self) -> Vec<Uuid> {
+        self.paid.lock().map(|paid| paid.clone()).unwrap_or_default()
+    }
+}
+
+impl PayoutProvider for StubPayoutProvider {
+    fn pay_out(&self, withdrawal: Withdrawal) -> PayoutFuture {
+        if self.failing.load(Ordering::SeqCst) {
+            return Box::pin(ready(Err("Payout provider unavailable".to_string())));
+        }
+        if let Ok(mut paid) = self.paid.lock() {
+            paid.push(withdrawal.id);
+        }
+        Box::pin(ready(Ok(format!("stub-{}", withdrawal.id))))
+    }
+}
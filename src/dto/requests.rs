@@ -1,6 +1,6 @@
 use serde::Deserialize;
 use uuid::Uuid;
-use crate::domain::Side;
+use crate::domain::{AuctionKind, MarketConfig, Peg, PostOnly, SelfTradePrevention, Side, TimeInForce, TrailBy};
 
 #[derive(Deserialize)]
 pub struct AuthRequest {
@@ -11,15 +11,80 @@ pub struct AuthRequest {
 #[derive(Deserialize)]
 pub struct OnRampHttpRequest {
     pub user_email: String,
-    pub balance: u64,
-    pub holding: u64,
+    pub asset: String,
+    pub amount: u64,
+}
+
+#[derive(Deserialize)]
+pub struct LedgerRequest {
+    pub user_email: String,
+}
+
+/// `destination` is handed to the payout provider as is, an account number or an address
+#[derive(Deserialize)]
+pub struct WithdrawRequest {
+    pub user_email: String,
+    pub asset: String,
+    pub amount: u64,
+    pub destination: String,
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawalsRequest {
+    pub user_email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PendingWithdrawalsRequest {
+    pub admin_email: String,
+}
+
+/// `approve: false` rejects the withdrawal, `reason` is kept on it
+#[derive(Deserialize)]
+pub struct ReviewWithdrawalRequest {
+    pub admin_email: String,
+    pub withdrawal_id: Uuid,
+    pub approve: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SelfTradePreventionRequest {
+    pub user_email: String,
+    /// `None` clears the account setting so orders fall back to the default mode
+    pub mode: Option<SelfTradePrevention>,
 }
 
 #[derive(Deserialize)]
 pub struct OrderInput {
     pub qty: u64,
+    #[serde(default)]
     pub price: u64,
     pub side: Side,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Makes the order an iceberg that only shows this much of `qty` at a time
+    #[serde(default)]
+    pub display_qty: Option<u64>,
+    /// Good-til-time expiry in unix milliseconds, GTC orders only
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Pegs a limit order to the book, `price` is then ignored
+    #[serde(default)]
+    pub peg: Option<Peg>,
+    /// Overrides the account's self-trade prevention mode for this order
+    #[serde(default)]
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    /// Only trade the whole quantity at once
+    #[serde(default)]
+    pub all_or_none: bool,
+    /// Smallest quantity to fill on entry and per counterparty once resting
+    #[serde(default)]
+    pub min_qty: Option<u64>,
+    /// Keeps a limit order out of the published book
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 #[derive(Deserialize)]
@@ -27,6 +92,8 @@ pub struct CreateLimitOrderRequest {
     pub market_id: u64,
     pub user_email: String,
     pub order: OrderInput,
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
 }
 
 #[derive(Deserialize)]
@@ -34,18 +101,101 @@ pub struct CreateMarketOrderRequest {
     pub market_id: u64,
     pub user_email: String,
     pub order: OrderInput,
+    /// Highest price a buy (lowest price a sell) may trade at
+    #[serde(default)]
+    pub worst_price: Option<u64>,
+    /// Furthest the order may trade from the opposite best at entry, 100 = 1%
+    #[serde(default)]
+    pub max_slippage_bps: Option<u64>,
 }
 
 #[derive(Deserialize)]
-pub struct CancelOrderRequest {
+pub struct StopOrderInput {
+    pub qty: u64,
+    pub side: Side,
+    pub stop_price: u64,
+    /// Leave out for a stop-market order
+    #[serde(default)]
+    pub limit_price: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateStopOrderRequest {
     pub market_id: u64,
+    pub user_email: String,
+    pub order: StopOrderInput,
+}
+
+#[derive(Deserialize)]
+pub struct TrailingStopOrderInput {
+    pub qty: u64,
     pub side: Side,
+    pub trail: TrailBy,
+    /// Leave out to fire a market order, otherwise the limit sits this far beyond the stop price
+    #[serde(default)]
+    pub limit_offset: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTrailingStopOrderRequest {
+    pub market_id: u64,
+    pub user_email: String,
+    pub order: TrailingStopOrderInput,
+}
+
+#[derive(Deserialize)]
+pub struct CancelOrderRequest {
+    pub market_id: u64,
+    pub user_email: String,
     pub order_id: Uuid,
 }
 
+#[derive(Deserialize)]
+pub struct OpenOrdersRequest {
+    pub user_email: String,
+}
+
+/// Leave out `market_id` or `side` to cancel across every market or on both sides
+#[derive(Deserialize)]
+pub struct CancelAllRequest {
+    pub user_email: String,
+    #[serde(default)]
+    pub market_id: Option<u64>,
+    #[serde(default)]
+    pub side: Option<Side>,
+}
+
+/// Leave out `price` or `qty` to keep the current value. `qty` is the new total working quantity.
+#[derive(Deserialize)]
+pub struct AmendOrderRequest {
+    pub market_id: u64,
+    pub user_email: String,
+    pub order_id: Uuid,
+    #[serde(default)]
+    pub price: Option<u64>,
+    #[serde(default)]
+    pub qty: Option<u64>,
+}
+
 #[derive(Deserialize)]
 pub struct CreateMarketRequest {
     pub market_id: u64,
+    pub base_asset: String,
+    pub quote_asset: String,
+    /// Matching policy, tick and lot size and order limits, each optional
+    #[serde(flatten)]
+    pub config: MarketConfig,
+}
+
+#[derive(Deserialize)]
+pub struct StartAuctionRequest {
+    pub market_id: u64,
+    pub kind: AuctionKind,
+}
+
+#[derive(Deserialize)]
+pub struct EndAuctionRequest {
+    pub market_id: u64,
 }
 
 #[derive(Deserialize)]
@@ -7,7 +7,8 @@ use axum::{
 };
 use serde::Serialize;
 use serde_json::json;
-use crate::domain::{Order, Trade};
+use uuid::Uuid;
+use crate::domain::{AssetBalance, ConservationReport, Order, OrderState, Transaction, PostOnlyOutcome, Trade, TradingPhase, Uncross, Withdrawal};
 
 /// Used by `/signup` and `/signin` routes
 #[derive(Serialize)]
@@ -58,8 +59,8 @@ impl AuthResponse {
 #[derive(Serialize)]
 pub struct OnRampResponse {
     pub message: String,
-    pub new_balance: u64,
-    pub new_holdings: u64,
+    /// Every balance of the user after the credit, keyed by asset
+    pub balances: BTreeMap<String, AssetBalance>,
     #[serde(skip_serializing)]
     pub status: StatusCode,
 }
@@ -68,28 +69,151 @@ impl IntoResponse for OnRampResponse {
     fn into_response(self) -> Response {
         let body = Json(json!({
             "message": self.message,
-            "new_balance": self.new_balance,
-            "new_holdings": self.new_holdings
+            "balances": self.balances
         }));
         (self.status, body).into_response()
     }
 }
 
 impl OnRampResponse {
-    pub fn ok(msg: impl Into<String>, new_balance: u64, new_holdings: u64) -> Self {
+    pub fn ok(msg: impl Into<String>, balances: BTreeMap<String, AssetBalance>) -> Self {
         Self {
             message: msg.into(),
-            new_balance,
-            new_holdings,
+            balances,
             status: StatusCode::ACCEPTED,
         }
     }
 
-    pub fn err(msg: impl Into<String>, new_balance: u64, new_holdings: u64) -> Self {
+    pub fn err(msg: impl Into<String>, balances: BTreeMap<String, AssetBalance>) -> Self {
+        Self {
+            message: msg.into(),
+            balances,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Used by `/ledger` route
+#[derive(Serialize)]
+pub struct LedgerResponse {
+    pub message: String,
+    pub transactions: Vec<Transaction>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl IntoResponse for LedgerResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "transactions": self.transactions
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+impl LedgerResponse {
+    pub fn ok(msg: impl Into<String>, transactions: Vec<Transaction>) -> Self {
+        Self {
+            message: msg.into(),
+            transactions,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            transactions: vec![],
+            status: StatusCode::NOT_FOUND,
+        }
+    }
+
+    pub fn internal_server_error(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            transactions: vec![],
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Used by `/ledger/check` route, answers 409 when the books do not balance
+#[derive(Serialize)]
+pub struct LedgerCheckResponse {
+    pub message: String,
+    pub report: Option<ConservationReport>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl IntoResponse for LedgerCheckResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "report": self.report
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+impl LedgerCheckResponse {
+    pub fn checked(report: ConservationReport) -> Self {
+        let (message, status) = if report.conserved {
+            ("Ledger balances, every asset is conserved", StatusCode::OK)
+        } else {
+            ("Ledger does not balance", StatusCode::CONFLICT)
+        };
+
+        Self {
+            message: message.to_string(),
+            report: Some(report),
+            status,
+        }
+    }
+
+    pub fn internal_server_error(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            report: None,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Used by `/selftradeprevention` route
+#[derive(Serialize)]
+pub struct SelfTradePreventionResponse {
+    pub message: String,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl IntoResponse for SelfTradePreventionResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "message": self.message }));
+        (self.status, body).into_response()
+    }
+}
+
+impl SelfTradePreventionResponse {
+    pub fn ok(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            status: StatusCode::NOT_FOUND,
+        }
+    }
+
+    pub fn internal_server_error(msg: impl Into<String>) -> Self {
         Self {
             message: msg.into(),
-            new_balance,
-            new_holdings,
             status: StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -98,7 +222,11 @@ impl OnRampResponse {
 #[derive(Serialize)]
 pub struct CreateLimitOrderResponse {
     pub message: String,
+    pub order_id: Option<Uuid>,
     pub trades: Vec<Trade>,
+    pub remaining_qty: u64,
+    pub canceled_orders: Vec<Uuid>,
+    pub post_only: Option<PostOnlyOutcome>,
     #[serde(skip_serializing)]
     pub status: StatusCode,
 }
@@ -107,7 +235,11 @@ impl IntoResponse for CreateLimitOrderResponse {
     fn into_response(self) -> Response {
         let body = Json(serde_json::json!({
             "message": self.message,
-            "trades": self.trades
+            "order_id": self.order_id,
+            "trades": self.trades,
+            "remaining_qty": self.remaining_qty,
+            "canceled_orders": self.canceled_orders,
+            "post_only": self.post_only
         }));
         (self.status, body).into_response()
     }
@@ -116,7 +248,10 @@ impl IntoResponse for CreateLimitOrderResponse {
 #[derive(Serialize)]
 pub struct CreateMarketOrderResponse {
     pub message: String,
+    pub order_id: Option<Uuid>,
     pub trades: Vec<Trade>,
+    /// Quantity left unfilled and cancelled
+    pub remaining_qty: u64,
     #[serde(skip_serializing)]
     pub status: StatusCode,
 }
@@ -125,7 +260,9 @@ impl IntoResponse for CreateMarketOrderResponse {
     fn into_response(self) -> Response {
         let body = Json(serde_json::json!({
             "message": self.message,
-            "trades": self.trades
+            "order_id": self.order_id,
+            "trades": self.trades,
+            "remaining_qty": self.remaining_qty
         }));
         (self.status, body).into_response()
     }
@@ -133,33 +270,86 @@ impl IntoResponse for CreateMarketOrderResponse {
 
 impl CreateMarketOrderResponse{
     
-    pub fn created(msg: impl Into<String>, trades: Vec<Trade>) -> Self {
+    pub fn created(msg: impl Into<String>, order_id: Option<Uuid>, trades: Vec<Trade>, remaining_qty: u64) -> Self {
         Self { 
             message: msg.into(), 
-            trades: trades, 
+            order_id,
+            trades, 
+            remaining_qty,
             status: StatusCode::OK }
     }
     
-    pub fn failed(msg: impl Into<String>, trades: Vec<Trade>) -> Self {
+    pub fn failed(msg: impl Into<String>, order_id: Option<Uuid>) -> Self {
         Self { 
             message: msg.into(), 
+            order_id,
             trades: vec![], 
+            remaining_qty: 0,
             status: StatusCode::EXPECTATION_FAILED }
     }
 
-    pub fn error(msg: impl Into<String>, trades: Vec<Trade>) -> Self {
+    pub fn error(msg: impl Into<String>) -> Self {
         Self { 
             message: msg.into(), 
+            order_id: None,
             trades: vec![], 
+            remaining_qty: 0,
             status: StatusCode::INTERNAL_SERVER_ERROR }
     }
 }
 
+#[derive(Serialize)]
+pub struct CreateStopOrderResponse {
+    pub message: String,
+    pub order_id: Option<Uuid>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl IntoResponse for CreateStopOrderResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "order_id": self.order_id
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+impl CreateStopOrderResponse {
+    pub fn created(msg: impl Into<String>, order_id: Option<Uuid>) -> Self {
+        Self {
+            message: msg.into(),
+            order_id,
+            status: StatusCode::CREATED,
+        }
+    }
+
+    pub fn failed(msg: impl Into<String>, order_id: Option<Uuid>) -> Self {
+        Self {
+            message: msg.into(),
+            order_id,
+            status: StatusCode::EXPECTATION_FAILED,
+        }
+    }
+
+    pub fn error(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            order_id: None,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct GetOrderBookResponse {
     pub message: String,
     pub bids: Option<BTreeMap<u64, VecDeque<Order>>>,
     pub asks: Option<BTreeMap<u64, VecDeque<Order>>>,
+    pub phase: Option<TradingPhase>,
+    /// Where the book would uncross, only while an auction runs
+    pub indicative: Option<Uncross>,
     #[serde(skip_serializing)]
     pub status: StatusCode,
 }
@@ -169,7 +359,9 @@ impl IntoResponse for GetOrderBookResponse {
         let body = Json(json!({
             "message": self.message,
             "bids": self.bids,
-            "asks": self.asks
+            "asks": self.asks,
+            "phase": self.phase,
+            "indicative": self.indicative
         }));
         (self.status, body).into_response()
     }
@@ -196,6 +388,13 @@ impl CancelOrderResponse {
             status: StatusCode::NOT_FOUND,
         }
     }
+
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            status: StatusCode::FORBIDDEN,
+        }
+    }
 }
 
 impl IntoResponse for CancelOrderResponse {
@@ -205,6 +404,117 @@ impl IntoResponse for CancelOrderResponse {
     }
 }
 
+/// Used by `/openorders` route
+#[derive(Serialize)]
+pub struct OpenOrdersResponse {
+    pub message: String,
+    pub orders: Vec<OrderState>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl OpenOrdersResponse {
+    pub fn ok(msg: impl Into<String>, orders: Vec<OrderState>) -> Self {
+        Self {
+            message: msg.into(),
+            orders,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn internal_server_error(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            orders: vec![],
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for OpenOrdersResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "orders": self.orders
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+/// Used by `/cancelall` route
+#[derive(Serialize)]
+pub struct CancelAllResponse {
+    pub message: String,
+    pub canceled_orders: Vec<Uuid>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl CancelAllResponse {
+    pub fn ok(msg: impl Into<String>, canceled_orders: Vec<Uuid>) -> Self {
+        Self {
+            message: msg.into(),
+            canceled_orders,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn failed(msg: impl Into<String>, status: StatusCode) -> Self {
+        Self {
+            message: msg.into(),
+            canceled_orders: vec![],
+            status,
+        }
+    }
+}
+
+impl IntoResponse for CancelAllResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "canceled_orders": self.canceled_orders
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+#[derive(Serialize)]
+pub struct AmendOrderResponse {
+    pub message: String,
+    pub order_id: Option<Uuid>,
+    pub trades: Vec<Trade>,
+    pub remaining_qty: u64,
+    pub canceled_orders: Vec<Uuid>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl IntoResponse for AmendOrderResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "order_id": self.order_id,
+            "trades": self.trades,
+            "remaining_qty": self.remaining_qty,
+            "canceled_orders": self.canceled_orders
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+impl AmendOrderResponse {
+    pub fn failed(msg: impl Into<String>, status: StatusCode) -> Self {
+        Self {
+            message: msg.into(),
+            order_id: None,
+            trades: vec![],
+            remaining_qty: 0,
+            canceled_orders: vec![],
+            status,
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct CreateMarketResponse {
     pub message: String,
@@ -241,6 +551,50 @@ impl IntoResponse for CreateMarketResponse {
     }
 }
 
+#[derive(Serialize)]
+pub struct AuctionResponse {
+    pub message: String,
+    pub phase: Option<TradingPhase>,
+    pub uncross: Option<Uncross>,
+    pub trades: Vec<Trade>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl AuctionResponse {
+    pub fn ok(msg: impl Into<String>, phase: Option<TradingPhase>, uncross: Option<Uncross>, trades: Vec<Trade>) -> Self {
+        Self {
+            message: msg.into(),
+            phase,
+            uncross,
+            trades,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn failed(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            phase: None,
+            uncross: None,
+            trades: vec![],
+            status: StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for AuctionResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "phase": self.phase,
+            "uncross": self.uncross,
+            "trades": self.trades
+        }));
+        (self.status, body).into_response()
+    }
+}
+
 #[derive(Serialize)]
 pub struct ListMarketsResponse {
     pub message: String,
@@ -269,3 +623,122 @@ impl IntoResponse for ListMarketsResponse {
     }
 }
 
+
+/// Used by `/orders/{id}` route
+#[derive(Serialize)]
+pub struct GetOrderResponse {
+    pub message: String,
+    pub order: Option<OrderState>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl GetOrderResponse {
+    pub fn ok(msg: impl Into<String>, order: Option<OrderState>) -> Self {
+        Self {
+            message: msg.into(),
+            order,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            order: None,
+            status: StatusCode::NOT_FOUND,
+        }
+    }
+
+    pub fn internal_server_error(msg: impl Into<String>) -> Self {
+        Self {
+            message: msg.into(),
+            order: None,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for GetOrderResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "order": self.order
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+/// Used by `/withdraw` and `/withdrawals/review` routes
+#[derive(Serialize)]
+pub struct WithdrawalResponse {
+    pub message: String,
+    pub withdrawal: Option<Withdrawal>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl WithdrawalResponse {
+    pub fn ok(msg: impl Into<String>, withdrawal: Option<Withdrawal>) -> Self {
+        Self {
+            message: msg.into(),
+            withdrawal,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn failed(msg: impl Into<String>, status: StatusCode) -> Self {
+        Self {
+            message: msg.into(),
+            withdrawal: None,
+            status,
+        }
+    }
+}
+
+impl IntoResponse for WithdrawalResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "withdrawal": self.withdrawal
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+/// Used by `/withdrawals` and `/withdrawals/pending` routes
+#[derive(Serialize)]
+pub struct WithdrawalsResponse {
+    pub message: String,
+    pub withdrawals: Vec<Withdrawal>,
+    #[serde(skip_serializing)]
+    pub status: StatusCode,
+}
+
+impl WithdrawalsResponse {
+    pub fn ok(msg: impl Into<String>, withdrawals: Vec<Withdrawal>) -> Self {
+        Self {
+            message: msg.into(),
+            withdrawals,
+            status: StatusCode::OK,
+        }
+    }
+
+    pub fn failed(msg: impl Into<String>, status: StatusCode) -> Self {
+        Self {
+            message: msg.into(),
+            withdrawals: vec![],
+            status,
+        }
+    }
+}
+
+impl IntoResponse for WithdrawalsResponse {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "message": self.message,
+            "withdrawals": self.withdrawals
+        }));
+        (self.status, body).into_response()
+    }
+}
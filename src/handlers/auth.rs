@@ -67,21 +67,46 @@ pub async fn onramp_handler(
     
     let _ = db_tx.send(DbCommand::OnRamp {
         user_email: payload.user_email.clone(),
-        delta_balance: payload.balance,
-        delta_holdings: payload.holding,
+        asset: payload.asset,
+        amount: payload.amount,
         response_status: oneshot_tx
     }).await;
     
     match oneshot_rx.await {
         Ok(response) => {
             if response.status.contains("Successfull") {
-                crate::dto::OnRampResponse::ok(response.status, response.balance, response.holdings)
+                crate::dto::OnRampResponse::ok(response.status, response.balances)
             } else {
-                crate::dto::OnRampResponse::err(response.status, response.balance, response.holdings)
+                crate::dto::OnRampResponse::err(response.status, response.balances)
             }
         },
         Err(_) => {
-            crate::dto::OnRampResponse::err("Internal server Error", 0, 0)
+            crate::dto::OnRampResponse::err("Internal server Error", Default::default())
         } 
     }
-}
\ No newline at end of file
+}
+
+pub async fn self_trade_prevention_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<crate::dto::SelfTradePreventionRequest>
+) -> crate::dto::SelfTradePreventionResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::SetSelfTradePrevention {
+        user_email: payload.user_email,
+        mode: payload.mode,
+        response_status: oneshot_tx
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.status.contains("Successfull") {
+                crate::dto::SelfTradePreventionResponse::ok(response.status)
+            } else {
+                crate::dto::SelfTradePreventionResponse::not_found(response.status)
+            }
+        }
+        Err(_) => crate::dto::SelfTradePreventionResponse::internal_server_error("Internal server Error"),
+    }
+}
@@ -0,0 +1,38 @@
+use axum::{extract::State, Json};
+use tokio::sync::oneshot;
+use crate::app::AppState;
+use crate::actors::db::DbCommand;
+use crate::dto::{LedgerCheckResponse, LedgerRequest, LedgerResponse};
+
+pub async fn ledger_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<LedgerRequest>,
+) -> LedgerResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::GetLedger {
+        user_email: payload.user_email,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.user_exists => LedgerResponse::ok("Successful! Ledger of the user", response.transactions),
+        Ok(_) => LedgerResponse::not_found("User does not exist"),
+        Err(e) => LedgerResponse::internal_server_error(format!("Actor error: {}", e)),
+    }
+}
+
+pub async fn ledger_check_handler(State(state): State<AppState>) -> LedgerCheckResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::CheckLedger {
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(report) => LedgerCheckResponse::checked(report),
+        Err(e) => LedgerCheckResponse::internal_server_error(format!("Actor error: {}", e)),
+    }
+}
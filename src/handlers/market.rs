@@ -3,10 +3,9 @@ use tokio::sync::oneshot;
 use crate::app::AppState;
 use crate::actors::{db::DbCommand, orderbook::OrderbookCommand};
 use crate::dto::{
-    CreateMarketRequest, CreateMarketResponse, GetOrderBookRequest, GetOrderBookResponse,
-    ListMarketsResponse,
+    AuctionResponse, CreateMarketRequest, CreateMarketResponse, EndAuctionRequest, GetOrderBookRequest,
+    GetOrderBookResponse, ListMarketsResponse, StartAuctionRequest,
 };
-use crate::domain::{MarketBook, Side};
 
 pub async fn get_order_book_handler(
     State(state): State<AppState>,
@@ -33,13 +32,15 @@ pub async fn get_order_book_handler(
 
                 match oneshot_rx.await {
                     Ok(response) => {
-                        if response.status.contains("Success") {
+                        if response.accepted {
 
                             GetOrderBookResponse {
                                 status: StatusCode::OK,
                                 message: "Succesfully fetched the Order Book".to_string(),
                                 bids: response.bids,
-                                asks: response.asks
+                                asks: response.asks,
+                                phase: response.phase,
+                                indicative: response.uncross
                             }
 
                         } else {
@@ -47,7 +48,9 @@ pub async fn get_order_book_handler(
                                 status: StatusCode::NOT_FOUND, 
                                 message: "Error fetching Order Book".to_string(), 
                                 bids: None, 
-                                asks: None
+                                asks: None,
+                                phase: None,
+                                indicative: None
                             }
                         }
                     } 
@@ -56,7 +59,9 @@ pub async fn get_order_book_handler(
                             status: StatusCode::INTERNAL_SERVER_ERROR, 
                             message: e.to_string(), 
                             bids: None,
-                            asks: None
+                            asks: None,
+                            phase: None,
+                            indicative: None
                         }
                     }
                 }
@@ -65,7 +70,9 @@ pub async fn get_order_book_handler(
                     status: StatusCode::NOT_ACCEPTABLE, 
                     message: "User does not exist".to_string(), 
                     bids: None, 
-                    asks: None
+                    asks: None,
+                    phase: None,
+                    indicative: None
                 }
             }
         } 
@@ -74,7 +81,9 @@ pub async fn get_order_book_handler(
                 status: StatusCode::INTERNAL_SERVER_ERROR, 
                 message: e.to_string(), 
                 bids: None, 
-                asks: None
+                asks: None,
+                phase: None,
+                indicative: None
             }
         }
     }
@@ -90,13 +99,16 @@ pub async fn create_market_handler(
     let _ = ob_tx
         .send(OrderbookCommand::CreateMarket {
             market_id: payload.market_id,
+            base_asset: payload.base_asset,
+            quote_asset: payload.quote_asset,
+            config: payload.config,
             resp: tx,
         })
         .await;
 
     match rx.await {
         Ok(response) => {
-            if response.status.contains("created") {
+            if response.accepted {
                 CreateMarketResponse::created(response.status, response.market_ids)
             } else {
                 CreateMarketResponse::failed(response.status)
@@ -121,4 +133,56 @@ pub async fn list_markets_handler(
         }
         Err(e) => ListMarketsResponse::ok(format!("Actor error: {}", e), vec![]),
     }
-}
\ No newline at end of file
+}
+pub async fn start_auction_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<StartAuctionRequest>,
+) -> AuctionResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (tx, rx) = oneshot::channel();
+
+    let _ = ob_tx
+        .send(OrderbookCommand::StartAuction {
+            market_id: payload.market_id,
+            kind: payload.kind,
+            resp: tx,
+        })
+        .await;
+
+    match rx.await {
+        Ok(response) => {
+            if response.accepted {
+                AuctionResponse::ok(response.status, response.phase, response.uncross, response.fills)
+            } else {
+                AuctionResponse::failed(response.status)
+            }
+        }
+        Err(e) => AuctionResponse::failed(format!("Actor error: {}", e)),
+    }
+}
+
+pub async fn end_auction_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<EndAuctionRequest>,
+) -> AuctionResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (tx, rx) = oneshot::channel();
+
+    let _ = ob_tx
+        .send(OrderbookCommand::EndAuction {
+            market_id: payload.market_id,
+            resp: tx,
+        })
+        .await;
+
+    match rx.await {
+        Ok(response) => {
+            if response.accepted {
+                AuctionResponse::ok(response.status, response.phase, response.uncross, response.fills)
+            } else {
+                AuctionResponse::failed(response.status)
+            }
+        }
+        Err(e) => AuctionResponse::failed(format!("Actor error: {}", e)),
+    }
+}
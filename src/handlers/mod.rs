@@ -1,7 +1,11 @@
 pub mod auth;
 pub mod orders;
 pub mod market;
+pub mod ledger;
+pub mod withdrawals;
 
 pub use auth::*;
 pub use orders::*;
-pub use market::*;
\ No newline at end of file
+pub use market::*;
+pub use ledger::*;
+pub use withdrawals::*;
\ No newline at end of file
@@ -1,10 +1,12 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use uuid::Uuid;
 use tokio::sync::oneshot;
 use crate::app::AppState;
 use crate::actors::orderbook::OrderbookCommand;
 use crate::dto::{
-    CancelOrderRequest, CancelOrderResponse, CreateLimitOrderRequest, CreateLimitOrderResponse,
-    CreateMarketOrderRequest, CreateMarketOrderResponse,
+    AmendOrderRequest, AmendOrderResponse, CancelAllRequest, CancelAllResponse, CancelOrderRequest, CancelOrderResponse, CreateLimitOrderRequest, CreateLimitOrderResponse,
+    CreateMarketOrderRequest, CreateMarketOrderResponse, CreateStopOrderRequest,
+    CreateStopOrderResponse, CreateTrailingStopOrderRequest, GetOrderResponse, OpenOrdersRequest, OpenOrdersResponse,
 };
 
 pub async fn create_limit_order_handler(
@@ -21,21 +23,38 @@ pub async fn create_limit_order_handler(
         side: payload.order.side, 
         qty: payload.order.qty, 
         price: payload.order.price, 
+        display_qty: payload.order.display_qty,
+        expires_at: payload.order.expires_at,
+        peg: payload.order.peg,
+        self_trade_prevention: payload.order.self_trade_prevention,
+        all_or_none: payload.order.all_or_none,
+        min_qty: payload.order.min_qty,
+        hidden: payload.order.hidden,
+        time_in_force: payload.order.time_in_force,
+        post_only: payload.post_only,
         resp: oneshot_tx 
     }).await;
 
     match oneshot_rx.await {
         Ok(response) => {
-            if response.status.contains("Success") {
+            if response.accepted {
                 CreateLimitOrderResponse {
                     message: response.status,
+                    order_id: response.order_id,
                     trades: response.fills,
+                    remaining_qty: response.remaining_qty,
+                    canceled_orders: response.canceled_orders,
+                    post_only: response.post_only,
                     status: StatusCode::OK
                 }
             } else {
                 CreateLimitOrderResponse { 
                     message: response.status.to_string(), 
+                    order_id: response.order_id,
                     trades: vec![], 
+                    remaining_qty: response.remaining_qty,
+                    canceled_orders: response.canceled_orders,
+                    post_only: response.post_only,
                     status: StatusCode::EXPECTATION_FAILED
                 }
             }
@@ -43,7 +62,11 @@ pub async fn create_limit_order_handler(
         Err(_) => {
             CreateLimitOrderResponse {
                 message: "Error Creating Market Order".to_string(),
+                order_id: None,
                 trades: vec![],
+                remaining_qty: 0,
+                canceled_orders: vec![],
+                post_only: None,
                 status: StatusCode::INTERNAL_SERVER_ERROR
             }
         }
@@ -63,25 +86,85 @@ pub async fn create_market_order_handler(
         user_id: payload.user_email, 
         side: payload.order.side, 
         qty: payload.order.qty, 
+        worst_price: payload.worst_price,
+        max_slippage_bps: payload.max_slippage_bps,
+        self_trade_prevention: payload.order.self_trade_prevention,
+        all_or_none: payload.order.all_or_none,
+        min_qty: payload.order.min_qty,
         resp: oneshot_tx 
     }).await;
 
     match oneshot_rx.await {
         Ok(response) => {
             
-            if response.status.contains("Success"){
-                CreateMarketOrderResponse::created(response.status, response.fills)
+            if response.accepted {
+                CreateMarketOrderResponse::created(response.status, response.order_id, response.fills, response.remaining_qty)
             } else {
-                CreateMarketOrderResponse::failed(response.status, response.fills)
+                CreateMarketOrderResponse::failed(response.status, response.order_id)
             }
            
         }
-        Err(_) => {
-            CreateMarketOrderResponse::error(
-                "Internal Servor Error",
-                vec![]
-            )
+        Err(_) => CreateMarketOrderResponse::error("Internal Servor Error"),
+    }
+}
+
+pub async fn create_stop_order_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateStopOrderRequest>
+) -> CreateStopOrderResponse {
+
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::NewStopOrder {
+        market_id: payload.market_id,
+        user_id: payload.user_email,
+        side: payload.order.side,
+        qty: payload.order.qty,
+        stop_price: payload.order.stop_price,
+        limit_price: payload.order.limit_price,
+        resp: oneshot_tx
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.accepted {
+                CreateStopOrderResponse::created(response.status, response.order_id)
+            } else {
+                CreateStopOrderResponse::failed(response.status, response.order_id)
+            }
+        }
+        Err(_) => CreateStopOrderResponse::error("Internal Servor Error"),
+    }
+}
+
+pub async fn create_trailing_stop_order_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateTrailingStopOrderRequest>
+) -> CreateStopOrderResponse {
+
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::NewTrailingStopOrder {
+        market_id: payload.market_id,
+        user_id: payload.user_email,
+        side: payload.order.side,
+        qty: payload.order.qty,
+        trail: payload.order.trail,
+        limit_offset: payload.order.limit_offset,
+        resp: oneshot_tx
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.accepted {
+                CreateStopOrderResponse::created(response.status, response.order_id)
+            } else {
+                CreateStopOrderResponse::failed(response.status, response.order_id)
+            }
         }
+        Err(_) => CreateStopOrderResponse::error("Internal Servor Error"),
     }
 }
 
@@ -94,7 +177,7 @@ pub async fn cancel_order_handler(
 
     let _ = ob_tx.send(OrderbookCommand::CancelOrder {
         market_id: payload.market_id,
-        side: payload.side,
+        user_id: payload.user_email,
         order_id: payload.order_id,
         resp: oneshot_tx,
     }).await;
@@ -103,10 +186,117 @@ pub async fn cancel_order_handler(
         Ok(response) => {
             if response.canceled {
                 CancelOrderResponse::ok(response.status)
+            } else if response.status.contains("another user") {
+                CancelOrderResponse::forbidden(response.status)
             } else {
                 CancelOrderResponse::failed(response.status)
             }
         }
         Err(e) => CancelOrderResponse::failed(format!("Actor error: {}", e)),
     }
-}
\ No newline at end of file
+}
+
+pub async fn amend_order_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<AmendOrderRequest>,
+) -> AmendOrderResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::AmendOrder {
+        market_id: payload.market_id,
+        user_id: payload.user_email,
+        order_id: payload.order_id,
+        new_price: payload.price,
+        new_qty: payload.qty,
+        resp: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.accepted {
+                AmendOrderResponse {
+                    message: response.status,
+                    order_id: response.order_id,
+                    trades: response.fills,
+                    remaining_qty: response.remaining_qty,
+                    canceled_orders: response.canceled_orders,
+                    status: StatusCode::OK,
+                }
+            } else if response.status.contains("another user") {
+                AmendOrderResponse::failed(response.status, StatusCode::FORBIDDEN)
+            } else {
+                AmendOrderResponse::failed(response.status, StatusCode::EXPECTATION_FAILED)
+            }
+        }
+        Err(e) => AmendOrderResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn get_order_handler(
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> GetOrderResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::GetOrder {
+        order_id,
+        resp: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.accepted {
+                GetOrderResponse::ok(response.status, response.order_state)
+            } else {
+                GetOrderResponse::not_found(response.status)
+            }
+        }
+        Err(e) => GetOrderResponse::internal_server_error(format!("Actor error: {}", e)),
+    }
+}
+
+pub async fn open_orders_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<OpenOrdersRequest>,
+) -> OpenOrdersResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::ListOpenOrders {
+        user_id: payload.user_email,
+        resp: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => OpenOrdersResponse::ok(response.status, response.open_orders),
+        Err(e) => OpenOrdersResponse::internal_server_error(format!("Actor error: {}", e)),
+    }
+}
+
+pub async fn cancel_all_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<CancelAllRequest>,
+) -> CancelAllResponse {
+    let ob_tx = state.ob_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = ob_tx.send(OrderbookCommand::CancelAll {
+        user_id: payload.user_email,
+        market_id: payload.market_id,
+        side: payload.side,
+        resp: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) => {
+            if response.accepted {
+                CancelAllResponse::ok(response.status, response.canceled_orders)
+            } else {
+                CancelAllResponse::failed(response.status, StatusCode::NOT_FOUND)
+            }
+        }
+        Err(e) => CancelAllResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
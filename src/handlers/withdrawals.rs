@@ -0,0 +1,98 @@
+use axum::{extract::State, http::StatusCode, Json};
+use tokio::sync::oneshot;
+use crate::app::AppState;
+use crate::actors::db::DbCommand;
+use crate::dto::{PendingWithdrawalsRequest, ReviewWithdrawalRequest, WithdrawRequest, WithdrawalResponse, WithdrawalsRequest, WithdrawalsResponse};
+
+pub async fn withdraw_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<WithdrawRequest>,
+) -> WithdrawalResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::Withdraw {
+        user_email: payload.user_email,
+        asset: payload.asset,
+        amount: payload.amount,
+        destination: payload.destination,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.status.contains("Success") => WithdrawalResponse::ok(response.status, response.withdrawal),
+        Ok(response) => WithdrawalResponse::failed(&response.status, failure_status(&response.status)),
+        Err(e) => WithdrawalResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn review_withdrawal_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<ReviewWithdrawalRequest>,
+) -> WithdrawalResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::ReviewWithdrawal {
+        admin_email: payload.admin_email,
+        withdrawal_id: payload.withdrawal_id,
+        approve: payload.approve,
+        reason: payload.reason,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.status.contains("Success") => WithdrawalResponse::ok(response.status, response.withdrawal),
+        Ok(response) => WithdrawalResponse::failed(&response.status, failure_status(&response.status)),
+        Err(e) => WithdrawalResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn withdrawals_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<WithdrawalsRequest>,
+) -> WithdrawalsResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::ListWithdrawals {
+        user_email: payload.user_email,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.status.contains("Success") => WithdrawalsResponse::ok(response.status, response.withdrawals),
+        Ok(response) => WithdrawalsResponse::failed(&response.status, failure_status(&response.status)),
+        Err(e) => WithdrawalsResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn pending_withdrawals_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<PendingWithdrawalsRequest>,
+) -> WithdrawalsResponse {
+    let db_tx = state.db_tx.clone();
+    let (oneshot_tx, oneshot_rx) = oneshot::channel();
+
+    let _ = db_tx.send(DbCommand::PendingWithdrawals {
+        admin_email: payload.admin_email,
+        response_status: oneshot_tx,
+    }).await;
+
+    match oneshot_rx.await {
+        Ok(response) if response.status.contains("Success") => WithdrawalsResponse::ok(response.status, response.withdrawals),
+        Ok(response) => WithdrawalsResponse::failed(&response.status, failure_status(&response.status)),
+        Err(e) => WithdrawalsResponse::failed(format!("Actor error: {}", e), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// HTTP status for a withdrawal command the DB actor turned down
+fn failure_status(status: &str) -> StatusCode {
+    if status.contains("not found") {
+        StatusCode::NOT_FOUND
+    } else if status.contains("Only admins") {
+        StatusCode::FORBIDDEN
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
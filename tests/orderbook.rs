@@ -0,0 +1,500 @@
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::json;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+use order_books_rust::app::AppState;
+use order_books_rust::handlers::orders::{create_limit_order_handler, get_order_handler};
+use order_books_rust::actors::{start_db_actor, start_orderbook_actor, DbCommand, OrderbookCommand, OrderbookEvent, OrderbookResponse};
+use order_books_rust::domain::{AssetBalance, AuctionKind, CircuitBreaker, ManualClock, MarketConfig, OrderStatus, Peg, PegReference, Reopen, SelfTradePrevention, Side, StubPayoutProvider, TimeInForce, TradingPhase, TrailBy, Uncross, WithdrawalPolicy};
+
+const MARKET: u64 = 1;
+
+/// Limit order settings the tests change, the rest are left at their defaults
+#[derive(Default)]
+struct Limit {
+    /// `MARKET` when not set
+    market_id: Option<u64>,
+    time_in_force: TimeInForce,
+    expires_at: Option<u64>,
+    self_trade_prevention: Option<SelfTradePrevention>,
+    peg: Option<Peg>,
+    all_or_none: bool,
+    display_qty: Option<u64>,
+    hidden: bool,
+}
+
+/// Both actors wired together, driven by a manual clock
+struct Exchange {
+    db_tx: mpsc::Sender<DbCommand>,
+    ob_tx: mpsc::Sender<OrderbookCommand>,
+    clock: Arc<ManualClock>,
+    events: broadcast::Receiver<OrderbookEvent>,
+}
+
+impl Exchange {
+    /// Starts the actors with a BTC/USD market and no users
+    async fn start() -> Self {
+        let (db_tx, db_rx) = mpsc::channel(32);
+        tokio::spawn(start_db_actor(db_rx, db_tx.clone(), WithdrawalPolicy::default(), Arc::new(StubPayoutProvider::default())));
+        let (ob_tx, ob_rx) = mpsc::channel(32);
+        let (events_tx, events) = broadcast::channel(64);
+        let clock = Arc::new(ManualClock::new(1_000));
+        tokio::spawn(start_orderbook_actor(ob_rx, db_tx.clone(), events_tx, clock.clone()));
+
+        let exchange = Self { db_tx, ob_tx, clock, events };
+        exchange.create_market(MARKET, "BTC", "USD").await;
+        exchange
+    }
+
+    /// State the HTTP handlers run against, wired to these actors
+    fn state(&self) -> AppState {
+        AppState { db_tx: self.db_tx.clone(), ob_tx: self.ob_tx.clone(), events_tx: broadcast::channel(1).0 }
+    }
+
+    async fn create_market(&self, market_id: u64, base_asset: &str, quote_asset: &str) {
+        let response = self.create_market_with(market_id, base_asset, quote_asset, MarketConfig::default()).await;
+        assert_eq!(response.status, format!("Market {} created", market_id));
+    }
+
+    async fn create_market_with(&self, market_id: u64, base_asset: &str, quote_asset: &str, config: MarketConfig) -> OrderbookResponse {
+        self.send(|resp| OrderbookCommand::CreateMarket {
+            market_id,
+            base_asset: base_asset.to_string(),
+            quote_asset: quote_asset.to_string(),
+            config,
+            resp,
+        })
+        .await
+    }
+
+    async fn send(&self, command: impl FnOnce(oneshot::Sender<OrderbookResponse>) -> OrderbookCommand) -> OrderbookResponse {
+        let (tx, rx) = oneshot::channel();
+        self.ob_tx.send(command(tx)).await.expect("orderbook actor stopped");
+        rx.await.expect("orderbook actor dropped the command")
+    }
+
+    /// Signs the user up and credits them with `funds`
+    async fn user(&self, email: &str, funds: &[(&str, u64)]) {
+        let (tx, rx) = oneshot::channel();
+        self.db_tx
+            .send(DbCommand::Signup { email: email.to_string(), password: "pw".to_string(), response_status: tx })
+            .await
+            .unwrap();
+        rx.await.unwrap();
+
+        for (asset, amount) in funds {
+            let (tx, rx) = oneshot::channel();
+            self.db_tx
+                .send(DbCommand::OnRamp { user_email: email.to_string(), asset: asset.to_string(), amount: *amount, response_status: tx })
+                .await
+                .unwrap();
+            rx.await.unwrap();
+        }
+    }
+
+    async fn balance(&self, email: &str, asset: &str) -> AssetBalance {
+        let (tx, rx) = oneshot::channel();
+        self.db_tx.send(DbCommand::GetUser { user_email: email.to_string(), response_status: tx }).await.unwrap();
+        rx.await.unwrap().user.expect("user exists").balance(asset)
+    }
+
+    async fn limit(&self, user: &str, side: Side, qty: u64, price: u64) -> OrderbookResponse {
+        self.limit_with(user, side, qty, price, Limit::default()).await
+    }
+
+    async fn limit_with(&self, user: &str, side: Side, qty: u64, price: u64, limit: Limit) -> OrderbookResponse {
+        self.send(|resp| OrderbookCommand::NewLimitOrder {
+            market_id: limit.market_id.unwrap_or(MARKET),
+            user_id: user.to_string(),
+            side,
+            qty,
+            price,
+            display_qty: limit.display_qty,
+            expires_at: limit.expires_at,
+            peg: limit.peg,
+            time_in_force: limit.time_in_force,
+            post_only: None,
+            self_trade_prevention: limit.self_trade_prevention,
+            all_or_none: limit.all_or_none,
+            min_qty: None,
+            hidden: limit.hidden,
+            resp,
+        })
+        .await
+    }
+    async fn market(&self, user: &str, side: Side, qty: u64, max_slippage_bps: Option<u64>) -> OrderbookResponse {
+        self.send(|resp| OrderbookCommand::NewMarketOrder {
+            market_id: MARKET,
+            user_id: user.to_string(),
+            side,
+            qty,
+            worst_price: None,
+            max_slippage_bps,
+            self_trade_prevention: None,
+            all_or_none: false,
+            min_qty: None,
+            resp,
+        })
+        .await
+    }
+
+    async fn trailing_stop(&self, user: &str, side: Side, trail: TrailBy, limit_offset: Option<u64>) -> OrderbookResponse {
+        self.send(|resp| OrderbookCommand::NewTrailingStopOrder {
+            market_id: MARKET,
+            user_id: user.to_string(),
+            side,
+            qty: 1,
+            trail,
+            limit_offset,
+            resp,
+        })
+        .await
+    }
+
+    /// Orders queued at `price` as `GetBook` shows them, front first, with their shown and reserve quantity
+    async fn queue(&self, side: Side, price: u64) -> Vec<(Uuid, u64, u64)> {
+        let book = self.send(|resp| OrderbookCommand::GetBook { market_id: MARKET, resp }).await;
+        let levels = match side {
+            Side::Bid => book.bids,
+            Side::Ask => book.asks,
+        };
+        levels
+            .unwrap_or_default()
+            .remove(&price)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|o| (o.id, o.qty, o.reserve_qty))
+            .collect()
+    }
+
+    /// Resting quantity per price on one side of the market, as `GetBook` shows it
+    async fn levels(&self, side: Side) -> Vec<(u64, u64)> {
+        let book = self.send(|resp| OrderbookCommand::GetBook { market_id: MARKET, resp }).await;
+        let levels = match side {
+            Side::Bid => book.bids,
+            Side::Ask => book.asks,
+        };
+        levels
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(price, orders)| (price, orders.iter().map(|o| o.qty).sum()))
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn good_til_time_order_expires_on_the_clock_and_releases_its_funds() {
+    let mut exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 1_000)]).await;
+
+    let response = exchange.limit_with("alice", Side::Bid, 5, 100, Limit { expires_at: Some(1_500), ..Limit::default() }).await;
+    assert_eq!(response.status, "Success, resting remaining order");
+    assert_eq!(exchange.balance("alice", "USD").await, AssetBalance { available: 500, reserved: 500 });
+
+    exchange.clock.advance(1_000);
+    let event = tokio::time::timeout(Duration::from_secs(2), exchange.events.recv())
+        .await
+        .expect("no expiry within the sweep interval")
+        .unwrap();
+    let OrderbookEvent::OrderExpired { market_id, order } = event else {
+        panic!("expected an expiry, got {:?}", event);
+    };
+    assert_eq!((market_id, Some(order.id)), (MARKET, response.order_id));
+
+    // the sweep releases funds after publishing, a round trip waits for it to finish
+    let book = exchange.send(|resp| OrderbookCommand::GetBook { market_id: MARKET, resp }).await;
+    assert!(book.bids.unwrap_or_default().is_empty());
+    assert_eq!(exchange.balance("alice", "USD").await, AssetBalance { available: 1_000, reserved: 0 });
+}
+
+#[tokio::test]
+async fn reservation_failure_is_not_read_from_the_asset_name() {
+    let exchange = Exchange::start().await;
+    exchange.create_market(2, "BTC", "SuccessCoin").await;
+    exchange.user("alice", &[("USD", 1_000)]).await;
+
+    let request = json!({ "market_id": 2, "user_email": "alice", "order": { "side": "Bid", "qty": 5, "price": 100 } });
+    let response = create_limit_order_handler(State(exchange.state()), Json(serde_json::from_value(request).unwrap())).await;
+    assert_eq!(response.message, "Insufficient SuccessCoin balance");
+    assert_eq!(response.status, StatusCode::EXPECTATION_FAILED);
+
+    let order = get_order_handler(State(exchange.state()), Path(response.order_id.unwrap())).await;
+    assert_eq!(order.status, StatusCode::OK);
+    assert_eq!(order.order.map(|state| state.status), Some(OrderStatus::Rejected));
+
+    let book = exchange.send(|resp| OrderbookCommand::GetBook { market_id: 2, resp }).await;
+    assert!(book.bids.unwrap_or_default().is_empty());
+}
+
+#[tokio::test]
+async fn unfunded_stop_is_cancelled_and_the_order_that_fired_it_still_fills() {
+    let exchange = Exchange::start().await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("USD", 10_000)]).await;
+    // the refused settlement is not read from the user name either
+    exchange.user("Success@evil", &[("USD", 550)]).await;
+
+    exchange.limit("bob", Side::Ask, 1, 100).await;
+    exchange.limit("carol", Side::Bid, 1, 100).await;
+    let stop = exchange
+        .send(|resp| OrderbookCommand::NewStopOrder {
+            market_id: MARKET,
+            user_id: "Success@evil".to_string(),
+            side: Side::Bid,
+            qty: 5,
+            stop_price: 110,
+            limit_price: None,
+            resp,
+        })
+        .await;
+    assert_eq!(stop.status, "Success, stop order accepted");
+    exchange.limit("bob", Side::Ask, 1, 110).await;
+    exchange.limit("bob", Side::Ask, 5, 200).await;
+
+    // the fired stop needs 1000 USD for the asks at 200 and only locked 550
+    let response = exchange.limit("carol", Side::Bid, 1, 110).await;
+    assert_eq!(response.status, "Success, fully matched");
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(200, 5)]);
+    assert_eq!(exchange.balance("carol", "USD").await, AssetBalance { available: 9_790, reserved: 0 });
+    assert_eq!(exchange.balance("Success@evil", "USD").await, AssetBalance { available: 550, reserved: 0 });
+
+    let stop_id = stop.order_id.unwrap();
+    let state = exchange.send(|resp| OrderbookCommand::GetOrder { order_id: stop_id, resp }).await.order_state.unwrap();
+    assert_eq!(state.status, OrderStatus::Cancelled);
+    assert!(state.reason.is_some_and(|reason| reason.starts_with("Could not pay for its fill")));
+
+    // nothing is left blocking the level
+    exchange.limit("bob", Side::Ask, 1, 110).await;
+    let response = exchange.limit("carol", Side::Bid, 1, 110).await;
+    assert_eq!(response.status, "Success, fully matched");
+}
+
+#[tokio::test]
+async fn market_order_notional_that_overflows_is_rejected() {
+    let exchange = Exchange::start().await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("USD", 10_000)]).await;
+
+    exchange.limit("bob", Side::Ask, 8, 1 << 62).await;
+    let response = exchange.market("carol", Side::Bid, 8, None).await;
+    assert_eq!(response.status, "Order rejected, notional is too large");
+
+    // a slippage bound past the top of the price range only means the whole book
+    let response = exchange.market("carol", Side::Bid, 1, Some(u64::MAX)).await;
+    assert_eq!(response.status, "Insufficient USD balance");
+
+    // the actor is still running
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(1 << 62, 8)]);
+}
+
+#[tokio::test]
+async fn trailing_stop_distances_out_of_range_are_rejected() {
+    let exchange = Exchange::start().await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("USD", 10_000)]).await;
+    exchange.limit("bob", Side::Ask, 1, 100).await;
+    exchange.limit("carol", Side::Bid, 1, 100).await;
+
+    let response = exchange.trailing_stop("carol", Side::Bid, TrailBy::BasisPoints(u64::MAX / 2), None).await;
+    assert_eq!(response.status, "Trail cannot be more than 10000 basis points");
+
+    let response = exchange.trailing_stop("carol", Side::Bid, TrailBy::Amount(10), Some(u64::MAX)).await;
+    assert_eq!(response.status, "Trail and limit offset put the price out of range");
+
+    let response = exchange.trailing_stop("carol", Side::Bid, TrailBy::BasisPoints(10_000), None).await;
+    assert_eq!(response.status, "Success, trailing stop order accepted");
+    assert_eq!(exchange.balance("carol", "USD").await, AssetBalance { available: 9_700, reserved: 200 });
+}
+
+#[tokio::test]
+async fn fill_or_kill_stopped_by_self_trade_prevention_is_killed() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 10_000), ("BTC", 100)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+
+    exchange.limit("bob", Side::Ask, 3, 100).await;
+    exchange.limit("alice", Side::Ask, 5, 100).await;
+    exchange.limit("bob", Side::Ask, 5, 101).await;
+
+    // cancel-newest stops at alice's own ask after 3, so 5 cannot all fill
+    let response = exchange.limit_with("alice", Side::Bid, 5, 101, Limit { time_in_force: TimeInForce::Fok, ..Limit::default() }).await;
+    assert_eq!(response.status, "Order killed, FOK could not be fully filled");
+    assert!(response.fills.is_empty());
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(100, 8), (101, 5)]);
+}
+
+#[tokio::test]
+async fn order_cut_by_decrement_and_cancel_closes_once_the_rest_fills() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 1_000), ("BTC", 10)]).await;
+    exchange.user("bob", &[("USD", 1_000)]).await;
+
+    let ask = exchange.limit("alice", Side::Ask, 10, 100).await;
+    let self_trade_prevention = Some(SelfTradePrevention::DecrementAndCancel);
+    let bid = exchange.limit_with("alice", Side::Bid, 4, 100, Limit { self_trade_prevention, ..Limit::default() }).await;
+    assert_eq!(bid.status, "Success, remainder canceled by self-trade prevention");
+    assert_eq!(bid.remaining_qty, 0);
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(100, 6)]);
+    // the cut 4 BTC no longer need to be held
+    assert_eq!(exchange.balance("alice", "BTC").await, AssetBalance { available: 4, reserved: 6 });
+
+    let response = exchange.limit("bob", Side::Bid, 6, 100).await;
+    assert_eq!(response.status, "Success, fully matched");
+
+    let ask_id = ask.order_id.unwrap();
+    let state = exchange.send(|resp| OrderbookCommand::GetOrder { order_id: ask_id, resp }).await.order_state.unwrap();
+    assert_eq!((state.status, state.qty, state.filled_qty), (OrderStatus::Filled, 6, 6));
+    let open = exchange.send(|resp| OrderbookCommand::ListOpenOrders { user_id: "alice".to_string(), resp }).await;
+    assert!(open.open_orders.is_empty());
+    assert_eq!(exchange.balance("alice", "BTC").await, AssetBalance { available: 4, reserved: 0 });
+    assert_eq!(exchange.balance("alice", "USD").await, AssetBalance { available: 1_600, reserved: 0 });
+}
+
+#[tokio::test]
+async fn mid_peg_between_prices_near_the_top_of_the_range_does_not_overflow() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 1_000)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+
+    exchange.limit("alice", Side::Bid, 1, 100).await;
+    exchange.limit("bob", Side::Ask, 1, u64::MAX).await;
+    let peg = Some(Peg { reference: PegReference::Mid, offset: 0 });
+    let response = exchange.limit_with("bob", Side::Ask, 1, u64::MAX, Limit { peg, ..Limit::default() }).await;
+    assert!(response.accepted, "{}", response.status);
+
+    let mid = (u64::MAX / 2) + 51;
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(mid, 1), (u64::MAX, 1)]);
+}
+
+#[tokio::test]
+async fn circuit_breaker_durations_past_a_day_are_rejected() {
+    let exchange = Exchange::start().await;
+    let breaker = CircuitBreaker { move_bps: 500, window_ms: 1_000, halt_ms: 1_000, reopen: Reopen::Continuous };
+
+    for breaker in [
+        CircuitBreaker { window_ms: u64::MAX, ..breaker },
+        CircuitBreaker { halt_ms: u64::MAX, ..breaker },
+        CircuitBreaker { reopen: Reopen::Auction { duration_ms: u64::MAX }, ..breaker },
+    ] {
+        let config = MarketConfig { circuit_breaker: Some(breaker), ..MarketConfig::default() };
+        let response = exchange.create_market_with(2, "ETH", "USD", config).await;
+        assert_eq!(response.status, "Circuit breaker window, halt and reopening auction cannot be longer than a day");
+    }
+
+    let config = MarketConfig { circuit_breaker: Some(breaker), ..MarketConfig::default() };
+    assert!(exchange.create_market_with(2, "ETH", "USD", config).await.accepted);
+}
+
+#[tokio::test]
+async fn band_wider_than_the_price_range_lets_every_price_through() {
+    let exchange = Exchange::start().await;
+    let config = MarketConfig { dynamic_band_bps: Some(10_000 << 24), ..MarketConfig::default() };
+    assert!(exchange.create_market_with(2, "ETH", "USD", config).await.accepted);
+    exchange.user("alice", &[("USD", 1 << 42)]).await;
+    exchange.user("bob", &[("ETH", 100)]).await;
+
+    let market_id = Some(2);
+    exchange.limit_with("bob", Side::Ask, 1, 1 << 40, Limit { market_id, ..Limit::default() }).await;
+    let response = exchange.limit_with("alice", Side::Bid, 1, 1 << 40, Limit { market_id, ..Limit::default() }).await;
+    assert_eq!(response.status, "Success, fully matched");
+
+    // the band is wider than u64, so it covers the whole range instead of wrapping around
+    let response = exchange.limit_with("alice", Side::Bid, 1, 1 << 41, Limit { market_id, ..Limit::default() }).await;
+    assert!(response.accepted, "{}", response.status);
+}
+
+#[tokio::test]
+async fn all_or_none_order_that_cannot_fill_leaves_the_book_as_it_was() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 10_000)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.limit("bob", Side::Ask, 3, 100).await;
+    exchange.limit("bob", Side::Ask, 2, 101).await;
+
+    let response = exchange.limit_with("alice", Side::Bid, 10, 101, Limit { all_or_none: true, ..Limit::default() }).await;
+    assert!(response.fills.is_empty());
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(100, 3), (101, 2)]);
+    assert_eq!(exchange.levels(Side::Bid).await, vec![(101, 10)]);
+
+    let response = exchange.limit_with("alice", Side::Bid, 5, 101, Limit { all_or_none: true, ..Limit::default() }).await;
+    assert_eq!(response.status, "Success, fully matched");
+    assert!(exchange.levels(Side::Ask).await.is_empty());
+}
+
+#[tokio::test]
+async fn opening_auction_queues_orders_uncrosses_and_hands_over_to_continuous_trading() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 10_000)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("USD", 10_000)]).await;
+
+    let response = exchange.send(|resp| OrderbookCommand::StartAuction { market_id: MARKET, kind: AuctionKind::Opening, resp }).await;
+    assert_eq!(response.phase, Some(TradingPhase::Auction(AuctionKind::Opening)));
+
+    assert_eq!(exchange.limit("alice", Side::Bid, 5, 102).await.status, "Success, order queued for the auction");
+    assert_eq!(exchange.limit("bob", Side::Ask, 8, 100).await.status, "Success, order queued for the auction");
+    let book = exchange.send(|resp| OrderbookCommand::GetBook { market_id: MARKET, resp }).await;
+    let expected = Uncross { price: 100, volume: 5, imbalance: 3, imbalance_side: Some(Side::Ask) };
+    assert_eq!(book.uncross, Some(expected.clone()));
+
+    let response = exchange.send(|resp| OrderbookCommand::EndAuction { market_id: MARKET, resp }).await;
+    assert_eq!(response.status, "Success, auction uncrossed at 100 for 5");
+    assert_eq!(response.uncross, Some(expected));
+    assert_eq!(response.phase, Some(TradingPhase::Continuous));
+    assert_eq!(response.fills.iter().map(|t| (t.price, t.qty)).collect::<Vec<_>>(), vec![(100, 5)]);
+    assert_eq!(exchange.balance("alice", "USD").await, AssetBalance { available: 9_500, reserved: 0 });
+
+    // the rest of bob's ask now trades as soon as it is crossed
+    let response = exchange.limit("carol", Side::Bid, 3, 100).await;
+    assert_eq!(response.status, "Success, fully matched");
+    assert!(exchange.levels(Side::Ask).await.is_empty());
+    assert_eq!(exchange.balance("bob", "USD").await, AssetBalance { available: 800, reserved: 0 });
+}
+
+#[tokio::test]
+async fn refilled_iceberg_slice_goes_to_the_back_of_its_level() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 10_000)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("BTC", 100)]).await;
+
+    let iceberg = exchange.limit_with("bob", Side::Ask, 10, 100, Limit { display_qty: Some(4), ..Limit::default() }).await.order_id.unwrap();
+    let plain = exchange.limit("carol", Side::Ask, 3, 100).await.order_id.unwrap();
+    // only the display slice shows, the reserve is left out
+    assert_eq!(exchange.queue(Side::Ask, 100).await, vec![(iceberg, 4, 0), (plain, 3, 0)]);
+
+    let response = exchange.limit("alice", Side::Bid, 4, 100).await;
+    assert_eq!(response.fills.iter().map(|t| (t.maker_order_id, t.qty)).collect::<Vec<_>>(), vec![(iceberg, 4)]);
+    assert_eq!(exchange.queue(Side::Ask, 100).await, vec![(plain, 3, 0), (iceberg, 4, 0)]);
+
+    // the plain order that was behind the slice now fills first
+    let response = exchange.limit("alice", Side::Bid, 5, 100).await;
+    assert_eq!(response.fills.iter().map(|t| (t.maker_order_id, t.qty)).collect::<Vec<_>>(), vec![(plain, 3), (iceberg, 2)]);
+    assert_eq!(exchange.queue(Side::Ask, 100).await, vec![(iceberg, 2, 0)]);
+    assert_eq!(exchange.levels(Side::Ask).await, vec![(100, 2)]);
+}
+
+#[tokio::test]
+async fn hidden_order_queues_behind_displayed_orders_and_stays_out_of_the_book() {
+    let exchange = Exchange::start().await;
+    exchange.user("alice", &[("USD", 10_000)]).await;
+    exchange.user("bob", &[("BTC", 100)]).await;
+    exchange.user("carol", &[("BTC", 100)]).await;
+
+    let hidden = exchange.limit_with("bob", Side::Ask, 5, 100, Limit { hidden: true, ..Limit::default() }).await.order_id.unwrap();
+    let displayed = exchange.limit("carol", Side::Ask, 3, 100).await.order_id.unwrap();
+    assert_eq!(exchange.queue(Side::Ask, 100).await, vec![(displayed, 3, 0)]);
+
+    // the hidden order came first but only fills once the displayed one is gone
+    let response = exchange.limit("alice", Side::Bid, 4, 100).await;
+    assert_eq!(response.fills.iter().map(|t| (t.maker_order_id, t.qty)).collect::<Vec<_>>(), vec![(displayed, 3), (hidden, 1)]);
+    assert!(exchange.queue(Side::Ask, 100).await.is_empty());
+
+    let response = exchange.limit("alice", Side::Bid, 4, 100).await;
+    assert_eq!(response.fills.iter().map(|t| (t.maker_order_id, t.qty)).collect::<Vec<_>>(), vec![(hidden, 4)]);
+}
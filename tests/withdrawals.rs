@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use order_books_rust::actors::{start_db_actor, DbCommand};
+use order_books_rust::domain::{AssetBalance, StubPayoutProvider, Withdrawal, WithdrawalPolicy, WithdrawalStatus};
+
+/// The DB actor with one funded user, paying out without an admin up to 100 USD
+struct Bank {
+    db_tx: mpsc::Sender<DbCommand>,
+    payouts: Arc<StubPayoutProvider>,
+}
+
+impl Bank {
+    async fn start() -> Self {
+        let (db_tx, db_rx) = mpsc::channel(32);
+        let payouts = Arc::new(StubPayoutProvider::default());
+        let policy = WithdrawalPolicy {
+            admins: ["admin".to_string()].into(),
+            approval_thresholds: [("USD".to_string(), 100)].into(),
+        };
+        tokio::spawn(start_db_actor(db_rx, db_tx.clone(), policy, payouts.clone()));
+
+        let bank = Self { db_tx, payouts };
+        let (tx, rx) = oneshot::channel();
+        bank.db_tx
+            .send(DbCommand::Signup { email: "alice".to_string(), password: "pw".to_string(), response_status: tx })
+            .await
+            .unwrap();
+        rx.await.unwrap();
+        let (tx, rx) = oneshot::channel();
+        bank.db_tx
+            .send(DbCommand::OnRamp { user_email: "alice".to_string(), asset: "USD".to_string(), amount: 500, response_status: tx })
+            .await
+            .unwrap();
+        rx.await.unwrap();
+        bank
+    }
+
+    async fn withdraw(&self, amount: u64) -> (String, Withdrawal) {
+        let (tx, rx) = oneshot::channel();
+        self.db_tx
+            .send(DbCommand::Withdraw {
+                user_email: "alice".to_string(),
+                asset: "USD".to_string(),
+                amount,
+                destination: "bank account".to_string(),
+                response_status: tx,
+            })
+            .await
+            .unwrap();
+        let response = rx.await.unwrap();
+        (response.status, response.withdrawal.expect("withdrawal accepted"))
+    }
+
+    async fn approve(&self, withdrawal_id: Uuid) -> String {
+        let (tx, rx) = oneshot::channel();
+        self.db_tx
+            .send(DbCommand::ReviewWithdrawal {
+                admin_email: "admin".to_string(),
+                withdrawal_id,
+                approve: true,
+                reason: None,
+                response_status: tx,
+            })
+            .await
+            .unwrap();
+        rx.await.unwrap().status
+    }
+
+    /// Polls the withdrawal until its payout has come back
+    async fn settled(&self, withdrawal_id: Uuid) -> Withdrawal {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let (tx, rx) = oneshot::channel();
+                self.db_tx
+                    .send(DbCommand::ListWithdrawals { user_email: "alice".to_string(), response_status: tx })
+                    .await
+                    .unwrap();
+                let withdrawal = rx.await.unwrap().withdrawals.into_iter().find(|w| w.id == withdrawal_id).unwrap();
+                if !withdrawal.payout_in_flight {
+                    return withdrawal;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("payout never came back")
+    }
+
+    async fn balance(&self) -> AssetBalance {
+        let (tx, rx) = oneshot::channel();
+        self.db_tx.send(DbCommand::GetUser { user_email: "alice".to_string(), response_status: tx }).await.unwrap();
+        rx.await.unwrap().user.expect("user exists").balance("USD")
+    }
+}
+
+#[tokio::test]
+async fn payout_runs_off_the_actor_and_completes_the_withdrawal() {
+    let bank = Bank::start().await;
+
+    let (status, withdrawal) = bank.withdraw(50).await;
+    assert_eq!(status, "Success, withdrawal approved, payout in progress");
+    assert!(withdrawal.payout_in_flight);
+
+    let withdrawal = bank.settled(withdrawal.id).await;
+    assert_eq!(withdrawal.status, WithdrawalStatus::Completed);
+    assert_eq!(withdrawal.payout_reference, Some(format!("stub-{}", withdrawal.id)));
+    assert_eq!(bank.payouts.paid(), vec![withdrawal.id]);
+    assert_eq!(bank.balance().await, AssetBalance { available: 450, reserved: 0 });
+}
+
+#[tokio::test]
+async fn failed_payout_keeps_the_funds_held_until_approved_again() {
+    let bank = Bank::start().await;
+    bank.payouts.set_failing(true);
+
+    let (_, withdrawal) = bank.withdraw(60).await;
+    let withdrawal = bank.settled(withdrawal.id).await;
+    assert_eq!(withdrawal.status, WithdrawalStatus::Approved);
+    assert_eq!(withdrawal.reason.as_deref(), Some("Payout failed, Payout provider unavailable"));
+    assert_eq!(bank.balance().await, AssetBalance { available: 440, reserved: 60 });
+
+    bank.payouts.set_failing(false);
+    assert_eq!(bank.approve(withdrawal.id).await, "Success, withdrawal approved, payout in progress");
+    let withdrawal = bank.settled(withdrawal.id).await;
+    assert_eq!(withdrawal.status, WithdrawalStatus::Completed);
+    assert_eq!(bank.balance().await, AssetBalance { available: 440, reserved: 0 });
+}